@@ -0,0 +1,2 @@
+ALTER TABLE "color"
+DROP COLUMN "cooldown_multiplier";
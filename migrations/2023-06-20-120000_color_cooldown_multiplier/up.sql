@@ -0,0 +1,2 @@
+ALTER TABLE "color"
+ADD COLUMN "cooldown_multiplier" REAL NOT NULL DEFAULT 1;
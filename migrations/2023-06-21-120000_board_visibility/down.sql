@@ -0,0 +1,2 @@
+ALTER TABLE "board"
+DROP COLUMN "visibility";
@@ -0,0 +1,2 @@
+ALTER TABLE "board"
+ADD COLUMN "visibility" TEXT NOT NULL DEFAULT 'public';
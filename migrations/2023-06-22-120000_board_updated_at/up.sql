@@ -0,0 +1,7 @@
+ALTER TABLE "board"
+ADD COLUMN "updated_at" BIGINT;
+
+UPDATE "board" SET "updated_at" = "created_at";
+
+ALTER TABLE "board"
+ALTER COLUMN "updated_at" SET NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE "board"
+ADD COLUMN "archived" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+ALTER TABLE "board"
+ADD COLUMN "sector_size" INTEGER;
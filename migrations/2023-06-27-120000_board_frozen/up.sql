@@ -0,0 +1,2 @@
+ALTER TABLE "board"
+ADD COLUMN "frozen" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -1,10 +1,12 @@
+use std::str::FromStr;
+
 use futures_util::future;
 use serde::{Serialize, Serializer};
 use warp::{reject::Reject, Rejection};
 
 use crate::objects::{AuthedUser, User};
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
 pub enum Permission {
 	Info,
 	BoardsList,
@@ -12,43 +14,100 @@ pub enum Permission {
 	BoardsPost,
 	BoardsPatch,
 	BoardsDelete,
+	BoardsPurge,
+	BoardsRestore,
+	BoardsPrivate,
 	BoardsDataGet,
 	BoardsDataPatch,
+	BoardsSectorsGet,
 	BoardsUsers,
+	BoardsUsersPixelsList,
 	BoardsPixelsList,
 	BoardsPixelsGet,
 	BoardsPixelsPost,
+	BoardsPixelsDelete,
 	SocketCore,
 	SocketAuthentication,
+	SocketGzip,
+	ConfigReload,
 }
 
-impl Serialize for Permission {
-	fn serialize<S: Serializer>(
-		&self,
-		serializer: S,
-	) -> Result<S::Ok, S::Error> {
-		let permission_str = match self {
+impl Permission {
+	pub const ALL: [Permission; 22] = [
+		Self::Info,
+		Self::BoardsList,
+		Self::BoardsGet,
+		Self::BoardsPost,
+		Self::BoardsPatch,
+		Self::BoardsDelete,
+		Self::BoardsPurge,
+		Self::BoardsRestore,
+		Self::BoardsPrivate,
+		Self::BoardsDataGet,
+		Self::BoardsDataPatch,
+		Self::BoardsSectorsGet,
+		Self::BoardsUsers,
+		Self::BoardsUsersPixelsList,
+		Self::BoardsPixelsList,
+		Self::BoardsPixelsGet,
+		Self::BoardsPixelsPost,
+		Self::BoardsPixelsDelete,
+		Self::SocketCore,
+		Self::SocketAuthentication,
+		Self::SocketGzip,
+		Self::ConfigReload,
+	];
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
 			Self::Info => "info",
 			Self::BoardsList => "boards.list",
 			Self::BoardsGet => "boards.get",
 			Self::BoardsPost => "boards.post",
 			Self::BoardsPatch => "boards.patch",
 			Self::BoardsDelete => "boards.delete",
+			Self::BoardsPurge => "boards.purge",
+			Self::BoardsRestore => "boards.restore",
+			Self::BoardsPrivate => "boards.private",
 			Self::BoardsDataGet => "boards.data.get",
 			Self::BoardsDataPatch => "boards.data.patch",
+			Self::BoardsSectorsGet => "boards.sectors.get",
 			Self::BoardsUsers => "boards.users",
+			Self::BoardsUsersPixelsList => "boards.users.pixels.list",
 			Self::BoardsPixelsList => "boards.pixels.list",
 			Self::BoardsPixelsGet => "boards.pixels.get",
 			Self::BoardsPixelsPost => "boards.pixels.post",
+			Self::BoardsPixelsDelete => "boards.pixels.delete",
 			Self::SocketCore => "socket.core",
 			Self::SocketAuthentication => "socket.authentication",
-		};
+			Self::SocketGzip => "socket.gzip",
+			Self::ConfigReload => "config.reload",
+		}
+	}
+}
 
-		serializer.serialize_str(permission_str)
+impl FromStr for Permission {
+	type Err = ();
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.into_iter()
+			.find(|permission| permission.as_str() == value)
+			.ok_or(())
+	}
+}
+
+impl Serialize for Permission {
+	fn serialize<S: Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.as_str())
 	}
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub enum PermissionsError {
 	MissingPermission(Permission),
 }
@@ -57,7 +116,7 @@ impl Reject for PermissionsError {}
 
 pub fn with_permission(
 	permission: Permission
-) -> (impl Fn(AuthedUser) -> future::Ready<Result<AuthedUser, Rejection>> + Clone) {
+) -> impl Fn(AuthedUser) -> future::Ready<Result<AuthedUser, Rejection>> + Clone {
 	move |authed| {
 		let user = Option::<&User>::from(&authed)
 			.unwrap_or_default();
@@ -1,13 +1,22 @@
+use std::{
+	collections::HashMap,
+	str::FromStr,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
 use http::StatusCode;
 use jsonwebkey::JsonWebKey;
-use jsonwebtoken::{decode, decode_header, TokenData, Validation, Algorithm};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, TokenData, Validation};
 use jsonwebtoken::errors::Error as JWTError;
+use parking_lot::RwLock;
 use reqwest::Client;
 use serde::Deserialize;
 use url::Url;
 
+use crate::access::permissions::Permission;
 use crate::objects::User;
-use crate::config::CONFIG;
+use crate::config::{OidcIssuer, CONFIG};
 
 #[derive(Debug)]
 pub enum DiscoveryError {
@@ -33,7 +42,6 @@ pub struct Discovery {
 }
 
 impl Discovery {
-	// TODO: cache
 	pub async fn load(
 		discovery_url: Url,
 		client: &Client,
@@ -49,11 +57,10 @@ impl Discovery {
 					.json().await
 					.map_err(|_| DiscoveryError::InvalidConfigResponse)
 			},
-			code => Err(DiscoveryError::InvalidResponse),
+			_ => Err(DiscoveryError::InvalidResponse),
 		}
 	}
 
-	// TODO: cache
 	pub async fn jwks_keys(
 		&self,
 		client: &Client,
@@ -80,7 +87,7 @@ impl Discovery {
 					})
 					.map_err(|_| DiscoveryError::InvalidConfigResponse)
 			},
-			code => Err(DiscoveryError::InvalidResponse),
+			_ => Err(DiscoveryError::InvalidResponse),
 		}
 	}
 }
@@ -89,19 +96,50 @@ impl Discovery {
 pub struct Identity {
 	pub sub: String,
 	pub exp: u64,
+	#[serde(flatten)]
+	pub claims: HashMap<String, serde_json::Value>,
+}
+
+impl Identity {
+	/// Permissions granted by the configured permissions claim.
+	/// The claim can be an array of permissions or a space separated string
+	/// (like `scope`). Unknown permissions are ignored.
+	fn claimed_permissions(&self) -> impl Iterator<Item = Permission> + '_ {
+		let claim = CONFIG
+			.oidc_permissions_claim
+			.as_ref()
+			.and_then(|claim| self.claims.get(claim));
+
+		let names: Vec<&str> = match claim {
+			Some(serde_json::Value::String(names)) => names.split_whitespace().collect(),
+			Some(serde_json::Value::Array(names)) => {
+				names.iter().filter_map(serde_json::Value::as_str).collect()
+			},
+			_ => vec![],
+		};
+
+		names
+			.into_iter()
+			.filter_map(|name| Permission::from_str(name).ok())
+	}
 }
 
 impl From<Identity> for User {
 	fn from(identity: Identity) -> Self {
-		Self::from_id(identity.sub)
+		let permissions = identity.claimed_permissions().collect::<Vec<_>>();
+		let mut user = Self::from_id(identity.sub);
+		user.permissions.extend(permissions);
+		user
 	}
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub enum ValidationError {
 	JWTError(JWTError),
 	DiscoveryError(DiscoveryError),
 	NoValidKeys,
+	UnknownIssuer,
 }
 
 impl From<JWTError> for ValidationError {
@@ -134,15 +172,103 @@ unsafe fn find_key_by_algorithm(
 	}
 }
 
-pub async fn validate_token(
-	token: &str
-) -> Result<TokenData<Identity>, ValidationError> {
-	let client = Client::new();
-	let discovery_url = CONFIG.discovery_url();
+fn same_issuer(
+	a: &str,
+	b: &str,
+) -> bool {
+	a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+/// An issuer with and without a trailing slash.
+/// `Url` adds a slash to host-only issuers which tokens usually don't have.
+fn issuer_forms(issuer: &str) -> [String; 2] {
+	let issuer = issuer.trim_end_matches('/');
+	[issuer.to_owned(), format!("{}/", issuer)]
+}
+
+/// Find the configured issuer a token claims to be from.
+/// The claim is unverified at this point and is only used to select which
+/// issuer's keys should verify the token.
+fn find_issuer(
+	token: &str,
+	mut issuers: impl Iterator<Item = OidcIssuer>,
+) -> Result<OidcIssuer, ValidationError> {
+	#[derive(Deserialize)]
+	struct Claims {
+		iss: String,
+	}
+
+	let header = decode_header(token)?;
+	let mut validation = Validation::new(header.alg);
+	validation.insecure_disable_signature_validation();
+	validation.validate_exp = false;
+	validation.required_spec_claims.clear();
 
-	let discovery = Discovery::load(discovery_url, &client).await?;
-	let keys = discovery.jwks_keys(&client).await?;
-	let mut valid_keys = keys
+	let claims = decode::<Claims>(token, &DecodingKey::from_secret(&[]), &validation)?.claims;
+
+	issuers
+		.find(|issuer| same_issuer(issuer.issuer.as_str(), &claims.iss))
+		.ok_or(ValidationError::UnknownIssuer)
+}
+
+/// How long an issuer's keys are used before they are fetched again.
+const KEYS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// The shortest time between fetches of an issuer's keys when a token uses
+/// an unknown key.
+const KEYS_MIN_AGE: Duration = Duration::from_secs(60);
+
+lazy_static! {
+	static ref CLIENT: Client = Client::new();
+	static ref ISSUER_KEYS: RwLock<HashMap<String, Arc<IssuerKeys>>> = RwLock::new(HashMap::new());
+}
+
+/// The signing keys of an issuer, as found through discovery.
+struct IssuerKeys {
+	/// The issuer as given by its discovery document.
+	issuer: Url,
+	keys: Vec<JsonWebKey>,
+	fetched_at: Instant,
+}
+
+impl IssuerKeys {
+	async fn fetch(issuer: &OidcIssuer) -> Result<Self, DiscoveryError> {
+		let discovery = Discovery::load(issuer.discovery_url(), &CLIENT).await?;
+		let keys = discovery.jwks_keys(&CLIENT).await?;
+
+		Ok(Self {
+			issuer: discovery.issuer,
+			keys,
+			fetched_at: Instant::now(),
+		})
+	}
+}
+
+/// The keys of an issuer, fetched if they aren't cached or are too old.
+/// If `refresh` is set, cached keys are only used if they are very recent.
+async fn issuer_keys(
+	issuer: &OidcIssuer,
+	refresh: bool,
+) -> Result<Arc<IssuerKeys>, DiscoveryError> {
+	let cache_key = issuer.issuer.as_str().trim_end_matches('/').to_owned();
+	let max_age = if refresh { KEYS_MIN_AGE } else { KEYS_MAX_AGE };
+
+	let cached = ISSUER_KEYS.read().get(&cache_key).cloned();
+	if let Some(keys) = cached.filter(|keys| keys.fetched_at.elapsed() < max_age) {
+		return Ok(keys);
+	}
+
+	let keys = Arc::new(IssuerKeys::fetch(issuer).await?);
+	ISSUER_KEYS.write().insert(cache_key, Arc::clone(&keys));
+	Ok(keys)
+}
+
+/// Check a token's signature and claims against an issuer's keys.
+fn verify(
+	token: &str,
+	issuer: &OidcIssuer,
+	keys: &IssuerKeys,
+) -> Result<TokenData<Identity>, ValidationError> {
+	let mut valid_keys = keys.keys
 		.iter()
 		.filter(|key| key.algorithm.is_some());
 
@@ -162,13 +288,156 @@ pub async fn validate_token(
 			key.algorithm.unwrap_unchecked()
 		};
 
+		let issuers = [keys.issuer.as_str(), issuer.issuer.as_str()]
+			.into_iter()
+			.flat_map(issuer_forms)
+			.collect::<Vec<_>>();
+
+		let mut validation = Validation::new(algorithm.into());
+		validation.set_issuer(&issuers);
+		if let Some(ref audience) = issuer.audience {
+			validation.set_audience(&[audience]);
+		}
+
 		decode::<Identity>(
 			token,
 			&key.key.to_decoding_key(),
-			&Validation::new(algorithm.into()),
+			&validation,
 		)
 		.map_err(ValidationError::from)
 	} else {
 		Err(ValidationError::NoValidKeys)
 	}
 }
+
+pub async fn validate_token(
+	token: &str
+) -> Result<TokenData<Identity>, ValidationError> {
+	let issuer = find_issuer(token, CONFIG.oidc_issuers())?;
+	let keys = issuer_keys(&issuer, false).await?;
+
+	match verify(token, &issuer, &keys) {
+		// The issuer may have rotated its keys since they were cached.
+		Err(ValidationError::NoValidKeys) => {
+			let keys = issuer_keys(&issuer, true).await?;
+			verify(token, &issuer, &keys)
+		},
+		result => result,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{SystemTime, UNIX_EPOCH};
+
+	use jsonwebkey::Key;
+	use jsonwebtoken::{encode, EncodingKey, Header};
+	use serde_json::json;
+
+	use super::*;
+
+	fn issuer(
+		url: &str,
+		audience: Option<&str>,
+	) -> OidcIssuer {
+		OidcIssuer {
+			issuer: Url::parse(url).unwrap(),
+			audience: audience.map(str::to_owned),
+		}
+	}
+
+	fn keys(
+		issuer: &str,
+		id: &str,
+		secret: &[u8],
+	) -> IssuerKeys {
+		let mut key = JsonWebKey::new(Key::Symmetric { key: secret.to_vec().into() });
+		key.key_id = Some(id.to_owned());
+		key.algorithm = Some(jsonwebkey::Algorithm::HS256);
+
+		IssuerKeys {
+			issuer: Url::parse(issuer).unwrap(),
+			keys: vec![key],
+			fetched_at: Instant::now(),
+		}
+	}
+
+	fn token(
+		issuer: &str,
+		audience: Option<&str>,
+		id: &str,
+		secret: &[u8],
+	) -> String {
+		let exp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap()
+			.as_secs() + 60 * 60;
+
+		let mut claims = json!({ "sub": "user", "iss": issuer, "exp": exp });
+		if let Some(audience) = audience {
+			claims["aud"] = json!(audience);
+		}
+
+		let mut header = Header::new(Algorithm::HS256);
+		header.kid = Some(id.to_owned());
+		encode(&header, &claims, &EncodingKey::from_secret(secret)).unwrap()
+	}
+
+	fn issuers() -> [OidcIssuer; 2] {
+		[
+			issuer("https://a.example", None),
+			issuer("https://b.example/realms/pxls", Some("pxls")),
+		]
+	}
+
+	#[test]
+	fn validates_tokens_from_two_issuers() {
+		let issuers = issuers();
+		let keys_a = keys("https://a.example", "a", b"secret a");
+		let keys_b = keys("https://b.example/realms/pxls", "b", b"secret b");
+
+		// Url adds a trailing slash to the first issuer which the token lacks.
+		let token_a = token("https://a.example", None, "a", b"secret a");
+		let issuer_a = find_issuer(&token_a, issuers.iter().cloned()).unwrap();
+		assert_eq!(issuer_a.issuer, issuers[0].issuer);
+		let identity = verify(&token_a, &issuer_a, &keys_a).unwrap();
+		assert_eq!(identity.claims.sub, "user");
+
+		let token_b = token("https://b.example/realms/pxls", Some("pxls"), "b", b"secret b");
+		let issuer_b = find_issuer(&token_b, issuers.iter().cloned()).unwrap();
+		assert_eq!(issuer_b.issuer, issuers[1].issuer);
+		assert!(verify(&token_b, &issuer_b, &keys_b).is_ok());
+	}
+
+	#[test]
+	fn rejects_keys_of_other_issuers() {
+		let issuers = issuers();
+		let keys_b = keys("https://b.example/realms/pxls", "b", b"secret b");
+
+		let token_a = token("https://a.example", None, "a", b"secret a");
+		let result = verify(&token_a, &issuers[0], &keys_b);
+		assert!(matches!(result, Err(ValidationError::NoValidKeys)));
+
+		// signed by the second issuer's key id but not its secret
+		let forged = token("https://b.example/realms/pxls", Some("pxls"), "b", b"secret a");
+		let result = verify(&forged, &issuers[1], &keys_b);
+		assert!(matches!(result, Err(ValidationError::JWTError(_))));
+	}
+
+	#[test]
+	fn rejects_unknown_issuers() {
+		let token = token("https://c.example", None, "c", b"secret c");
+		let result = find_issuer(&token, issuers().into_iter());
+		assert!(matches!(result, Err(ValidationError::UnknownIssuer)));
+	}
+
+	#[test]
+	fn requires_the_issuer_audience() {
+		let issuers = issuers();
+		let keys_b = keys("https://b.example/realms/pxls", "b", b"secret b");
+
+		let token = token("https://b.example/realms/pxls", Some("other"), "b", b"secret b");
+		let result = verify(&token, &issuers[1], &keys_b);
+		assert!(matches!(result, Err(ValidationError::JWTError(_))));
+	}
+}
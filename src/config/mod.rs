@@ -1,22 +1,439 @@
+use std::{collections::HashMap, convert::TryFrom, fmt, fs, io, path::PathBuf, sync::Arc};
+
+use parking_lot::RwLock;
 use serde::Deserialize;
 use url::Url;
 
+use crate::webhook::EventKind;
+
+/// An OpenID issuer whose tokens are accepted.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "String")]
+pub struct OidcIssuer {
+	pub issuer: Url,
+	/// If set, tokens must include this in their audience.
+	pub audience: Option<String>,
+}
+
+impl TryFrom<String> for OidcIssuer {
+	type Error = url::ParseError;
+
+	/// Parse an issuer from its URL, optionally followed by `=` and an
+	/// audience. Issuer URLs have no query component so `=` is unambiguous.
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		let (issuer, audience) = match value.split_once('=') {
+			Some((issuer, audience)) => (issuer, Some(audience.to_owned())),
+			None => (value.as_str(), None),
+		};
+
+		Ok(Self {
+			issuer: Url::parse(issuer)?,
+			audience,
+		})
+	}
+}
+
+impl OidcIssuer {
+	pub fn discovery_url(&self) -> Url {
+		// join replaces the last path segment unless there's a trailing slash
+		let mut issuer = self.issuer.clone();
+		if !issuer.path().ends_with('/') {
+			issuer.set_path(&format!("{}/", issuer.path()));
+		}
+		issuer.join(".well-known/openid-configuration").unwrap()
+	}
+}
+
 #[derive(Deserialize)]
 pub struct Config {
+	#[allow(dead_code)]
 	pub host: String,
 	pub port: u16,
 	pub database_url: Url,
+	/// Which logs to output, as tracing filter directives such as `info` or
+	/// `pxls_rs=debug,warp=info`.
+	#[serde(default = "Config::default_log_level")]
+	pub log_level: String,
+	/// How many times to try connecting to the database at startup.
+	#[serde(default = "Config::default_database_connect_attempts")]
+	pub database_connect_attempts: u32,
+	/// The delay (in seconds) before the first database connection retry.
+	/// The delay doubles after each attempt.
+	#[serde(default = "Config::default_database_retry_delay")]
+	pub database_retry_delay: u64,
 	pub oidc_issuer: Url,
 	pub oidc_client_id: Option<String>,
+	/// Other issuers whose tokens are accepted, as a comma separated list.
+	/// Each is an issuer URL, optionally followed by `=` and an audience.
+	#[serde(default)]
+	pub oidc_additional_issuers: Vec<OidcIssuer>,
+	/// A token claim which grants the permissions it lists, either as an
+	/// array or a space separated string.
+	pub oidc_permissions_claim: Option<String>,
+	/// A file of `KEY=value` lines, read over the environment, which can
+	/// change the reloadable settings while running.
+	pub config_file: Option<PathBuf>,
+	/// A directory boards are backed up to before their data is destroyed.
+	/// No backups are made if unset.
+	pub backup_dir: Option<PathBuf>,
+	/// The board `/boards/default` redirects to.
+	/// If unset or missing, the board with the lowest id is used.
+	pub default_board: Option<usize>,
+	/// How long (in seconds) a successful placement is replayed for retries
+	/// with the same idempotency key.
+	#[serde(default = "Config::default_idempotency_key_ttl")]
+	pub idempotency_key_ttl: u64,
+	/// Whether users without a token can place pixels.
+	/// Anonymous users share a single cooldown.
+	#[serde(default)]
+	pub allow_anonymous_place: bool,
+	/// The most pixels a board can have.
+	#[serde(default = "Config::default_max_board_pixels")]
+	pub max_board_pixels: usize,
+	/// The most colors a board palette can have.
+	#[serde(default = "Config::default_max_palette_size")]
+	pub max_palette_size: usize,
+	/// The most positions that can be looked up in a single request.
+	#[serde(default = "Config::default_max_lookup_positions")]
+	pub max_lookup_positions: usize,
+	/// The largest body (in bytes) accepted when placing.
+	#[serde(default = "Config::default_max_placement_body_size")]
+	pub max_placement_body_size: u64,
+	/// The largest body (in bytes) accepted when patching board info.
+	#[serde(default = "Config::default_max_patch_body_size")]
+	pub max_patch_body_size: u64,
+	/// The largest body (in bytes) accepted when patching board data.
+	#[serde(default = "Config::default_max_data_patch_body_size")]
+	pub max_data_patch_body_size: u64,
+	/// The most sockets a single board accepts. Unlimited if unset.
+	pub max_sockets_per_board: Option<usize>,
+	/// The preferred number of positions in each sector of new or reshaped
+	/// boards. If unset, the last level of the board shape is used.
+	pub sector_size: Option<usize>,
+	/// How often (in seconds) sockets are sent a ping. Zero disables pings.
+	#[serde(default = "Config::default_socket_ping_interval")]
+	pub socket_ping_interval: u64,
+	/// How long (in seconds) a socket has to respond to a ping before it is
+	/// disconnected.
+	#[serde(default = "Config::default_socket_pong_timeout")]
+	pub socket_pong_timeout: u64,
+	/// Messages per second a socket can send on average before it is closed.
+	/// Zero disables the limit.
+	#[serde(default = "Config::default_socket_message_rate")]
+	pub socket_message_rate: u32,
+	/// Messages a socket can send in a burst above the average rate.
+	#[serde(default = "Config::default_socket_message_burst")]
+	pub socket_message_burst: u32,
+	/// The most placements replayed to a reconnecting socket.
+	#[serde(default = "Config::default_socket_replay_limit")]
+	pub socket_replay_limit: usize,
+	/// How long (in seconds) a board thumbnail can lag behind placements.
+	#[serde(default = "Config::default_thumbnail_max_age")]
+	pub thumbnail_max_age: u64,
+	/// How often (in seconds) sockets are sent the user count if it changed.
+	/// Zero disables sending user counts.
+	#[serde(default = "Config::default_user_count_interval")]
+	pub user_count_interval: u64,
+	/// URLs sent a JSON event when boards are created, patched or deleted,
+	/// as a comma separated list.
+	#[serde(default)]
+	pub webhook_urls: Vec<Url>,
+	/// Which events are sent to webhooks, as a comma separated list of
+	/// `board_created`, `board_patched` and `board_deleted`. All if unset.
+	pub webhook_events: Option<Vec<EventKind>>,
+	/// How many times delivering an event to a webhook is retried.
+	#[serde(default = "Config::default_webhook_retries")]
+	pub webhook_retries: u32,
+	/// How long (in seconds) a webhook has to respond to an event.
+	#[serde(default = "Config::default_webhook_timeout")]
+	pub webhook_timeout: u64,
 }
 
 impl Config {
-	pub fn discovery_url(&self) -> Url {
-		self.oidc_issuer.join(".well-known/openid-configuration").unwrap()
+	fn default_log_level() -> String {
+		"info".to_owned()
+	}
+
+	fn default_database_connect_attempts() -> u32 {
+		5
+	}
+
+	fn default_database_retry_delay() -> u64 {
+		1
+	}
+
+	fn default_idempotency_key_ttl() -> u64 {
+		5 * 60
+	}
+
+	fn default_max_board_pixels() -> usize {
+		4096 * 4096
+	}
+
+	fn default_max_palette_size() -> usize {
+		256
+	}
+
+	fn default_max_lookup_positions() -> usize {
+		1024
+	}
+
+	fn default_max_placement_body_size() -> u64 {
+		1024
+	}
+
+	fn default_max_patch_body_size() -> u64 {
+		64 * 1024
+	}
+
+	fn default_max_data_patch_body_size() -> u64 {
+		64 * 1024 * 1024
+	}
+
+	fn default_socket_ping_interval() -> u64 {
+		30
+	}
+
+	fn default_socket_pong_timeout() -> u64 {
+		10
+	}
+
+	fn default_socket_message_rate() -> u32 {
+		5
+	}
+
+	fn default_socket_message_burst() -> u32 {
+		20
+	}
+
+	fn default_socket_replay_limit() -> usize {
+		1000
+	}
+
+	fn default_thumbnail_max_age() -> u64 {
+		30
+	}
+
+	fn default_user_count_interval() -> u64 {
+		10
+	}
+
+	fn default_webhook_retries() -> u32 {
+		3
+	}
+
+	fn default_webhook_timeout() -> u64 {
+		10
+	}
+
+	/// All issuers whose tokens are accepted, starting with the primary one.
+	pub fn oidc_issuers(&self) -> impl Iterator<Item = OidcIssuer> + '_ {
+		let primary = OidcIssuer {
+			issuer: self.oidc_issuer.clone(),
+			audience: None,
+		};
+
+		std::iter::once(primary).chain(self.oidc_additional_issuers.iter().cloned())
+	}
+}
+
+/// Settings which can be reloaded while running.
+#[derive(Deserialize, Debug)]
+pub struct Tunables {
+	/// How long (in seconds) after placing a user is still considered active.
+	#[serde(default = "Tunables::default_idle_timeout")]
+	pub idle_timeout: u32,
+	/// Placement requests allowed per user (or address) each window.
+	/// Zero disables the limit.
+	#[serde(default = "Tunables::default_placement_rate_limit")]
+	pub placement_rate_limit: u32,
+	/// The placement rate limit window in seconds.
+	#[serde(default = "Tunables::default_placement_rate_window")]
+	pub placement_rate_window: u64,
+	/// The time (in seconds) it takes for each pixel to become available.
+	#[serde(default = "Tunables::default_cooldown")]
+	pub cooldown: u32,
+	/// The time (in seconds) for each successive pixel to become available, as
+	/// a comma separated list. Pixels past the end of the list use the last
+	/// value. If empty, every pixel uses `cooldown`.
+	#[serde(default)]
+	pub cooldown_curve: Vec<u32>,
+	/// Extra cooldown (in seconds) per pixel for each earlier placement at the
+	/// position of a user's last placement. Zero disables density scaling.
+	#[serde(default)]
+	pub cooldown_density_penalty: u32,
+}
+
+impl Tunables {
+	fn default_idle_timeout() -> u32 {
+		5 * 60
+	}
+
+	fn default_placement_rate_limit() -> u32 {
+		10
+	}
+
+	fn default_placement_rate_window() -> u64 {
+		10
+	}
+
+	fn default_cooldown() -> u32 {
+		30
+	}
+
+	/// The cooldown (in seconds) of a pixel slot, where slot 0 is the first
+	/// pixel to become available after placing.
+	pub fn slot_cooldown(
+		&self,
+		slot: usize,
+	) -> u32 {
+		self.cooldown_curve
+			.get(slot)
+			.or_else(|| self.cooldown_curve.last())
+			.copied()
+			.unwrap_or(self.cooldown)
+	}
+
+	/// Read the settings from the environment and the config file.
+	fn load() -> Result<Self, ReloadError> {
+		let file = match CONFIG.config_file {
+			Some(ref path) => Some(fs::read_to_string(path).map_err(ReloadError::Io)?),
+			None => None,
+		};
+
+		Self::parse(std::env::vars(), file.as_deref()).map_err(ReloadError::Invalid)
+	}
+
+	/// Parse the settings from environment variables and the contents of a
+	/// config file, with the file taking precedence.
+	fn parse(
+		variables: impl IntoIterator<Item = (String, String)>,
+		file: Option<&str>,
+	) -> Result<Self, envy::Error> {
+		// NOTE: envy ignores the case of keys, so they are merged by their
+		// lowercase form to avoid passing the same field twice.
+		let mut merged = variables
+			.into_iter()
+			.map(|(key, value)| (key.to_lowercase(), value))
+			.collect::<HashMap<_, _>>();
+
+		if let Some(file) = file {
+			merged.extend(
+				file.lines()
+					.map(str::trim)
+					.filter(|line| !line.is_empty() && !line.starts_with('#'))
+					.filter_map(|line| line.split_once('='))
+					.map(|(key, value)| (key.trim().to_lowercase(), value.trim().to_owned())),
+			);
+		}
+
+		envy::from_iter(merged)
+	}
+}
+
+#[derive(Debug)]
+pub enum ReloadError {
+	Io(io::Error),
+	Invalid(envy::Error),
+}
+
+impl fmt::Display for ReloadError {
+	fn fmt(
+		&self,
+		formatter: &mut fmt::Formatter,
+	) -> fmt::Result {
+		match self {
+			Self::Io(err) => write!(formatter, "failed to read config file: {}", err),
+			Self::Invalid(err) => write!(formatter, "invalid config: {}", err),
+		}
 	}
 }
 
 lazy_static! {
 	pub static ref CONFIG: Config = envy::from_env::<Config>()
 		.expect("Incomplete config setup");
+	static ref TUNABLES: RwLock<Arc<Tunables>> = RwLock::new(Arc::new(
+		Tunables::load().expect("Incomplete config setup"),
+	));
+}
+
+/// The current reloadable settings.
+pub fn tunables() -> Arc<Tunables> {
+	Arc::clone(&TUNABLES.read())
+}
+
+/// Read the reloadable settings again. They are left as they were on error.
+pub fn reload() -> Result<(), ReloadError> {
+	let tunables = Tunables::load()?;
+	tracing::info!(?tunables, "reloaded config");
+	*TUNABLES.write() = Arc::new(tunables);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn variables(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+		pairs
+			.iter()
+			.map(|(key, value)| (key.to_string(), value.to_string()))
+			.collect()
+	}
+
+	#[test]
+	fn file_overrides_environment() {
+		let environment = variables(&[("COOLDOWN", "10"), ("IDLE_TIMEOUT", "60")]);
+		let file = "# overrides\nCOOLDOWN = 20\n";
+
+		let tunables = Tunables::parse(environment, Some(file)).unwrap();
+
+		assert_eq!(tunables.cooldown, 20);
+		assert_eq!(tunables.idle_timeout, 60);
+	}
+
+	#[test]
+	fn keys_are_merged_regardless_of_case() {
+		let environment = variables(&[("COOLDOWN", "10"), ("cooldown", "15")]);
+		let file = "Cooldown=20";
+
+		let tunables = Tunables::parse(environment, Some(file)).unwrap();
+
+		assert_eq!(tunables.cooldown, 20);
+	}
+
+	#[test]
+	fn environment_is_used_without_a_file() {
+		let environment = variables(&[("COOLDOWN_CURVE", "5,10,20")]);
+
+		let tunables = Tunables::parse(environment, None).unwrap();
+
+		assert_eq!(tunables.cooldown_curve, vec![5, 10, 20]);
+		assert_eq!(tunables.slot_cooldown(1), 10);
+		assert_eq!(tunables.slot_cooldown(5), 20);
+	}
+
+	#[test]
+	fn idle_timeout_defaults_to_five_minutes() {
+		let tunables = Tunables::parse(Vec::new(), None).unwrap();
+
+		assert_eq!(tunables.idle_timeout, 5 * 60);
+	}
+
+	#[test]
+	fn idle_timeout_is_configurable() {
+		let environment = variables(&[("IDLE_TIMEOUT", "90")]);
+		let tunables = Tunables::parse(environment, None).unwrap();
+		assert_eq!(tunables.idle_timeout, 90);
+
+		let tunables = Tunables::parse(Vec::new(), Some("IDLE_TIMEOUT=45")).unwrap();
+		assert_eq!(tunables.idle_timeout, 45);
+	}
+
+	#[test]
+	fn invalid_idle_timeouts_are_rejected() {
+		let environment = variables(&[("IDLE_TIMEOUT", "soon")]);
+
+		assert!(Tunables::parse(environment, None).is_err());
+	}
 }
@@ -11,6 +11,13 @@ pub struct Board {
 	pub created_at: i64,
 	pub shape: serde_json::Value,
 	pub max_stacked: i32,
+	pub visibility: String,
+	pub updated_at: i64,
+	pub archived: bool,
+	pub placement_cap: Option<i32>,
+	pub sector_size: Option<i32>,
+	pub cooldown: Option<i32>,
+	pub frozen: bool,
 }
 
 #[derive(Insertable)]
@@ -20,6 +27,12 @@ pub struct NewBoard {
 	pub created_at: i64,
 	pub shape: serde_json::Value,
 	pub max_stacked: i32,
+	pub visibility: String,
+	pub updated_at: i64,
+	pub placement_cap: Option<i32>,
+	pub sector_size: Option<i32>,
+	pub cooldown: Option<i32>,
+	pub frozen: bool,
 }
 
 #[derive(Queryable, Insertable, Identifiable, Associations)]
@@ -31,6 +44,7 @@ pub struct Color {
 	pub index: i32,
 	pub name: String,
 	pub value: i32,
+	pub cooldown_multiplier: f32,
 }
 
 #[derive(Queryable, QueryableByName, Identifiable, Associations, Serialize, Debug, Clone)]
@@ -5,8 +5,27 @@ use crate::{database::schema, objects::Board};
 
 pub fn load_boards(connection: &mut Connection) -> QueryResult<Vec<Board>> {
 	schema::board::table
+		.filter(schema::board::archived.eq(false))
 		.load(connection)?
 		.into_iter()
 		.map(|board| Board::load(board, connection))
 		.collect()
 }
+
+/// Unarchive a board, returning it if it was archived.
+pub fn restore_board(
+	board_id: i32,
+	connection: &mut Connection,
+) -> QueryResult<Option<Board>> {
+	diesel::update(schema::board::table)
+		.filter(
+			schema::board::id
+				.eq(board_id)
+				.and(schema::board::archived.eq(true)),
+		)
+		.set(schema::board::archived.eq(false))
+		.get_result(connection)
+		.optional()?
+		.map(|board| Board::load(board, connection))
+		.transpose()
+}
@@ -5,6 +5,13 @@ table! {
 		created_at -> Int8,
 		shape -> Jsonb,
 		max_stacked -> Int4,
+		visibility -> Text,
+		updated_at -> Int8,
+		archived -> Bool,
+		placement_cap -> Nullable<Int4>,
+		sector_size -> Nullable<Int4>,
+		cooldown -> Nullable<Int4>,
+		frozen -> Bool,
 	}
 }
 
@@ -23,6 +30,7 @@ table! {
 		index -> Int4,
 		name -> Text,
 		value -> Int4,
+		cooldown_multiplier -> Float4,
 	}
 }
 
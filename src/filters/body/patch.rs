@@ -1,4 +1,5 @@
 use bytes::Bytes;
+use serde::de::DeserializeOwned;
 
 use super::*;
 use crate::filters::header::content_range::{self, ContentRange};
@@ -63,9 +64,10 @@ impl BinaryPatch {
 	}
 }
 
-// TODO: size limit
-pub fn bytes() -> impl Filter<Extract = (BinaryPatch,), Error = Rejection> + Copy {
+/// A binary patch body of at most `limit` bytes.
+pub fn bytes(limit: u64) -> impl Filter<Extract = (BinaryPatch,), Error = Rejection> + Copy {
 	warp::patch()
+		.and(warp::body::content_length_limit(limit))
 		.and(warp::body::bytes())
 		.and(warp::header::exact(
 			header::CONTENT_TYPE.as_str(),
@@ -78,3 +80,57 @@ pub fn bytes() -> impl Filter<Extract = (BinaryPatch,), Error = Rejection> + Cop
 }
 
 // TODO: multipart patch?
+
+pub const MERGE_PATCH_TYPE: &str = "application/merge-patch+json";
+
+#[derive(Debug, Clone, Copy)]
+pub enum InvalidMergePatch {
+	UnsupportedMediaType,
+	Malformed,
+}
+
+impl Reject for InvalidMergePatch {}
+
+impl Reply for InvalidMergePatch {
+	fn into_response(self) -> reply::Response {
+		match self {
+			Self::UnsupportedMediaType => {
+				Response::builder()
+					.status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+					.header("accept-patch", MERGE_PATCH_TYPE)
+					.body("".into())
+					.unwrap()
+			},
+			Self::Malformed => StatusCode::BAD_REQUEST.into_response(),
+		}
+	}
+}
+
+/// A JSON Merge Patch (RFC 7396) body.
+/// The content type must be `application/merge-patch+json` and the body at
+/// most `limit` bytes.
+pub fn merge<T>(limit: u64) -> impl Filter<Extract = (T,), Error = Rejection> + Copy
+where
+	T: DeserializeOwned + Send + 'static,
+{
+	warp::patch()
+		.and(warp::header::optional::<String>(header::CONTENT_TYPE.as_str()))
+		.and_then(|content_type: Option<String>| async move {
+			let media_type = content_type
+				.as_deref()
+				.and_then(|value| value.split(';').next())
+				.map(str::trim);
+
+			match media_type {
+				Some(media_type) if media_type.eq_ignore_ascii_case(MERGE_PATCH_TYPE) => Ok(()),
+				_ => Err(warp::reject::custom(InvalidMergePatch::UnsupportedMediaType)),
+			}
+		})
+		.untuple_one()
+		.and(warp::body::content_length_limit(limit))
+		.and(warp::body::bytes())
+		.and_then(|bytes: Bytes| async move {
+			serde_json::from_slice(&bytes)
+				.map_err(|_| warp::reject::custom(InvalidMergePatch::Malformed))
+		})
+}
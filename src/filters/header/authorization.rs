@@ -9,6 +9,7 @@ pub enum BearerError {
 	Invalid,
 	MissingScheme,
 	MissingToken,
+	#[allow(dead_code)]
 	ValidationError(ValidationError),
 }
 
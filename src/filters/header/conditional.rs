@@ -0,0 +1,48 @@
+use std::time::SystemTime;
+
+use super::*;
+
+/// The conditional request headers relevant to cache validation.
+pub struct Conditions {
+	pub if_none_match: Option<String>,
+	pub if_modified_since: Option<SystemTime>,
+}
+
+fn opaque_tag(tag: &str) -> &str {
+	tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+impl Conditions {
+	/// Whether the client's cached copy of a resource with the given
+	/// validators is still current.
+	pub fn unmodified(
+		&self,
+		etag: &str,
+		last_modified: SystemTime,
+	) -> bool {
+		// If-None-Match takes precedence over If-Modified-Since (RFC 9110 §13.1.3).
+		if let Some(ref if_none_match) = self.if_none_match {
+			if_none_match
+				.split(',')
+				.map(str::trim)
+				.any(|tag| tag == "*" || opaque_tag(tag) == opaque_tag(etag))
+		} else if let Some(since) = self.if_modified_since {
+			last_modified <= since
+		} else {
+			false
+		}
+	}
+}
+
+pub fn conditions() -> impl Filter<Extract = (Conditions,), Error = Rejection> + Copy {
+	warp::header::optional::<String>(header::IF_NONE_MATCH.as_str())
+		.and(warp::header::optional::<String>(header::IF_MODIFIED_SINCE.as_str()))
+		.map(|if_none_match, if_modified_since: Option<String>| {
+			Conditions {
+				if_none_match,
+				// invalid dates are ignored as per RFC 9110 §13.1.3
+				if_modified_since: if_modified_since
+					.and_then(|date| httpdate::parse_http_date(&date).ok()),
+			}
+		})
+}
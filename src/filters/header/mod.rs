@@ -4,6 +4,7 @@ use super::*;
 
 pub mod accept_encoding;
 pub mod authorization;
+pub mod conditional;
 pub mod content_range;
 pub mod range;
 
@@ -56,17 +56,20 @@ pub enum HttpRange {
 }
 
 impl HttpRange {
+	/// Resolve the range against data of some length.
+	/// Ranges which extend past the end are truncated, but ranges which
+	/// don't overlap the data at all can't be satisfied.
 	fn with_length(
 		&self,
 		length: usize,
 	) -> Result<OpsRange<usize>, RangeIndexError> {
 		let range = match self {
-			Self::FromEndToLast(from_end) => length - from_end..length,
+			Self::FromEndToLast(from_end) => length.saturating_sub(*from_end)..length,
 			Self::FromStartToLast(range) => range.start..length,
-			Self::FromStartToEnd(range) => range.clone(),
+			Self::FromStartToEnd(range) => range.start..range.end.min(length),
 		};
 
-		if range.end <= length {
+		if range.start < range.end {
 			Ok(range)
 		} else {
 			Err(RangeIndexError::TooLarge(length))
@@ -74,6 +77,15 @@ impl HttpRange {
 	}
 }
 
+/// The content-range value for part of some data.
+/// HTTP ranges are inclusive, unlike rust ones.
+fn content_range(
+	range: &OpsRange<usize>,
+	length: usize,
+) -> String {
+	format!("bytes {}-{}/{}", range.start, range.end - 1, length)
+}
+
 struct DataRange {
 	data: Vec<u8>,
 	range: OpsRange<usize>,
@@ -107,12 +119,12 @@ where
 	let mut efficient_ranges = vec![iter.next().unwrap()];
 
 	for range in iter {
-		let mut current_range = efficient_ranges.last_mut().unwrap();
+		let current_range = efficient_ranges.last_mut().unwrap();
 		let gap = range.start.saturating_sub(current_range.end);
 
 		if gap < inbetween_threshold {
-			// extend last range
-			current_range.end = range.end;
+			// extend last range (which may already contain this one)
+			current_range.end = current_range.end.max(range.end);
 		} else {
 			// start a new range
 			efficient_ranges.push(range);
@@ -123,9 +135,7 @@ where
 		.into_iter()
 		.map(|range| {
 			let length = range.end - range.start;
-			let mut subdata: Vec<u8> = std::iter::repeat(0)
-				.take(length)
-				.collect();
+			let mut subdata: Vec<u8> = vec![0; length];
 
 			data.seek(std::io::SeekFrom::Start(
 				u64::try_from(range.start).unwrap(),
@@ -143,12 +153,11 @@ where
 
 fn choose_boundary(datas: &[DataRange]) -> String {
 	fn random_boundary_string() -> String {
-		let random_string = rand::thread_rng()
-			.sample_iter::<char, _>(rand::distributions::Standard)
-			.take(8)
-			.collect::<String>();
-
-		format!("--{}", random_string)
+		rand::thread_rng()
+			.sample_iter(rand::distributions::Alphanumeric)
+			.take(16)
+			.map(char::from)
+			.collect::<String>()
 	}
 
 	let mut boundary = random_boundary_string();
@@ -170,25 +179,27 @@ fn choose_boundary(datas: &[DataRange]) -> String {
 fn merge_ranges(
 	datas: &[DataRange],
 	boundary: &str,
+	length: usize,
 ) -> Vec<u8> {
 	let mut joined = Vec::new();
 
 	for DataRange { data, range } in datas {
 		joined.extend_from_slice(
 			format!(
-				"{}\r\n\
+				"--{}\r\n\
 				content-type: application/octet-stream\r\n\
-				content-range: bytes {}-{}/{}\r\n\r\n",
+				content-range: {}\r\n\r\n",
 				boundary,
-				range.start, range.end, data.len(),
+				content_range(range, length),
 			)
 			.as_bytes(),
 		);
 
 		joined.extend_from_slice(data);
+		joined.extend_from_slice(b"\r\n");
 	}
 
-	joined.extend_from_slice(boundary.as_bytes());
+	joined.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
 
 	joined
 }
@@ -233,23 +244,20 @@ impl TryFrom<&str> for Range {
 							.map_err(RangeParseError::ValueParseError)
 					},
 					(start, end) => {
-						start.parse()
-							.and_then(|start| {
-								end.parse()
-									.map(|end| HttpRange::FromStartToEnd(start..end))
-							})
-							.map_err(RangeParseError::ValueParseError)
+						let start: usize = start.parse()
+							.map_err(RangeParseError::ValueParseError)?;
+						let end: usize = end.parse()
+							.map_err(RangeParseError::ValueParseError)?;
+
+						if end < start {
+							Err(RangeParseError::Backwards)
+						} else {
+							// the last position is inclusive
+							Ok(HttpRange::FromStartToEnd(start..end + 1))
+						}
 					},
 				}?;
-				if let HttpRange::FromStartToEnd(range) = &http_range {
-					if range.end < range.start {
-						Err(RangeParseError::Backwards)
-					} else {
-						Ok(http_range)
-					}
-				} else {
-					Ok(http_range)
-				}
+				Ok(http_range)
 			})
 			.collect::<Result<_, _>>()?;
 
@@ -279,7 +287,7 @@ impl Range {
 				match data_ranges(data, unit, ranges) {
 					Ok(datas) => {
 						let boundary = choose_boundary(&datas);
-						let merged = merge_ranges(&datas, &boundary);
+						let merged = merge_ranges(&datas, &boundary, data.len());
 
 						Response::builder()
 							.status(StatusCode::PARTIAL_CONTENT)
@@ -306,8 +314,7 @@ impl Range {
 
 				match result {
 					Ok(range) => {
-						let length = range.end - range.start;
-						let mut buffer = vec![0; length];
+						let mut buffer = vec![0; range.end - range.start];
 
 						data.seek(std::io::SeekFrom::Start(
 							u64::try_from(range.start).unwrap(),
@@ -327,8 +334,8 @@ impl Range {
 						.into_response();
 						response = reply::with_header(
 							response,
-							header::CONTENT_TYPE,
-							format!("bytes {}-{}/{}", range.start, range.end, length),
+							header::CONTENT_RANGE,
+							content_range(&range, data.len()),
 						)
 						.into_response();
 						response
@@ -371,3 +378,151 @@ pub fn range() -> impl Filter<Extract = (Range,), Error = Rejection> + Copy {
 			.map_err(warp::reject::custom)
 	})
 }
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use warp::hyper::body::to_bytes;
+
+	use super::*;
+	use crate::objects::sector_cache::Len;
+
+	impl Len for Cursor<Vec<u8>> {
+		fn len(&self) -> usize {
+			self.get_ref().len()
+		}
+	}
+
+	fn data(length: u8) -> Cursor<Vec<u8>> {
+		Cursor::new((0..length).collect())
+	}
+
+	fn respond(
+		value: &str,
+		length: u8,
+	) -> reply::Response {
+		let range = Range::try_from(value).ok().unwrap();
+		range.respond_with(&mut data(length))
+	}
+
+	#[test]
+	fn ranges_are_parsed() {
+		assert!(matches!(
+			Range::try_from("bytes=2-5"),
+			Ok(Range::Single { unit, range: HttpRange::FromStartToEnd(range) })
+				if unit == "bytes" && range == (2..6)
+		));
+		assert!(matches!(
+			Range::try_from("bytes=4-"),
+			Ok(Range::Single { range: HttpRange::FromStartToLast(range), .. })
+				if range.start == 4
+		));
+		assert!(matches!(
+			Range::try_from("bytes=-3"),
+			Ok(Range::Single { range: HttpRange::FromEndToLast(3), .. })
+		));
+		assert!(matches!(
+			Range::try_from("bytes=0-1,8-9"),
+			Ok(Range::Multi { ranges, .. }) if ranges.len() == 2
+		));
+	}
+
+	#[test]
+	fn malformed_ranges_are_rejected() {
+		assert!(matches!(Range::try_from("0-3"), Err(RangeParseError::MissingUnit)));
+		assert!(matches!(Range::try_from("bytes=3"), Err(RangeParseError::MissingHyphenMinus(_))));
+		assert!(matches!(Range::try_from("bytes=-"), Err(RangeParseError::RangeEmpty)));
+		assert!(matches!(Range::try_from("bytes=a-"), Err(RangeParseError::ValueParseError(_))));
+		assert!(matches!(Range::try_from("bytes=5-3"), Err(RangeParseError::Backwards)));
+	}
+
+	#[tokio::test]
+	async fn single_ranges_respond_with_that_part() {
+		let response = respond("bytes=2-5", 10);
+
+		assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+		assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+		let body = to_bytes(response.into_body()).await.unwrap();
+		assert_eq!(&body[..], &[2, 3, 4, 5]);
+	}
+
+	#[tokio::test]
+	async fn ranges_past_the_end_are_truncated() {
+		let response = respond("bytes=6-100", 10);
+		assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 6-9/10");
+		let body = to_bytes(response.into_body()).await.unwrap();
+		assert_eq!(&body[..], &[6, 7, 8, 9]);
+
+		let response = respond("bytes=-20", 10);
+		assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 0-9/10");
+		let body = to_bytes(response.into_body()).await.unwrap();
+		assert_eq!(body.len(), 10);
+	}
+
+	#[test]
+	fn unsatisfiable_ranges_are_rejected() {
+		for value in ["bytes=10-", "bytes=10-20", "bytes=0-1,10-"] {
+			let response = respond(value, 10);
+
+			assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE, "{}", value);
+			assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+		}
+	}
+
+	#[test]
+	fn unknown_units_are_rejected() {
+		for value in ["lines=0-1", "lines=0-1,4-5"] {
+			let response = respond(value, 10);
+
+			assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", value);
+		}
+	}
+
+	#[tokio::test]
+	async fn multiple_ranges_respond_with_each_part() {
+		let response = respond("bytes=60-61,0-1", 100);
+
+		assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+		let content_type = response.headers()[header::CONTENT_TYPE]
+			.to_str()
+			.unwrap()
+			.to_owned();
+		let boundary = content_type
+			.strip_prefix("multipart/byteranges; boundary=")
+			.unwrap();
+
+		let body = to_bytes(response.into_body()).await.unwrap();
+		let mut expected = Vec::new();
+		for (range, data) in [("0-1", [0, 1]), ("60-61", [60, 61])] {
+			expected.extend_from_slice(
+				format!(
+					"--{}\r\n\
+					content-type: application/octet-stream\r\n\
+					content-range: bytes {}/100\r\n\r\n",
+					boundary, range,
+				)
+				.as_bytes(),
+			);
+			expected.extend_from_slice(&data);
+			expected.extend_from_slice(b"\r\n");
+		}
+		expected.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+		assert_eq!(&body[..], &expected[..]);
+	}
+
+	#[test]
+	fn nearby_ranges_are_merged() {
+		let ranges = [
+			HttpRange::FromStartToEnd(8..10),
+			HttpRange::FromStartToEnd(0..2),
+			HttpRange::FromStartToEnd(1..4),
+		];
+
+		let datas = data_ranges(&mut data(100), "bytes", &ranges).ok().unwrap();
+
+		assert_eq!(datas.len(), 1);
+		assert_eq!(datas[0].range, 0..10);
+		assert_eq!(datas[0].data, (0..10).collect::<Vec<u8>>());
+	}
+}
@@ -1,5 +1,6 @@
 pub mod body;
 pub mod header;
+pub mod rate_limit;
 pub mod resource;
 
 use std::{convert::Infallible, num::ParseIntError};
@@ -0,0 +1,135 @@
+use std::{
+	collections::HashMap,
+	net::{IpAddr, SocketAddr},
+	time::{Duration, Instant},
+};
+
+use http::header;
+use parking_lot::Mutex;
+
+use super::*;
+use crate::objects::{AuthedUser, User};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimited {
+	retry_after: Duration,
+}
+
+impl Reject for RateLimited {}
+
+impl Reply for RateLimited {
+	fn into_response(self) -> reply::Response {
+		let retry_after = self.retry_after.as_secs_f64().ceil() as u64;
+
+		Response::builder()
+			.status(StatusCode::TOO_MANY_REQUESTS)
+			.header(header::RETRY_AFTER, retry_after)
+			.body("".into())
+			.unwrap()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+	User(String),
+	Address(IpAddr),
+	Unknown,
+}
+
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+struct State {
+	buckets: HashMap<Key, Bucket>,
+	last_sweep: Instant,
+}
+
+/// A token bucket per user (or address for anonymous requests) which allows
+/// `limit` requests each `window`.
+pub struct RateLimiter {
+	/// The current limit and window, so that they can change while running.
+	limits: fn() -> (u32, Duration),
+	state: Mutex<State>,
+}
+
+impl RateLimiter {
+	/// A limit or window of zero disables limiting.
+	pub fn new(limits: fn() -> (u32, Duration)) -> Self {
+		Self {
+			limits,
+			state: Mutex::new(State {
+				buckets: HashMap::new(),
+				last_sweep: Instant::now(),
+			}),
+		}
+	}
+
+	/// Take a token from the bucket of `key`, or only check that one is
+	/// available if `consume` is false.
+	fn acquire(
+		&self,
+		key: Key,
+		consume: bool,
+	) -> Result<(), RateLimited> {
+		let (limit, window) = (self.limits)();
+		if limit == 0 || window.is_zero() {
+			return Ok(());
+		}
+
+		let now = Instant::now();
+		let capacity = f64::from(limit);
+		let rate = capacity / window.as_secs_f64();
+
+		let mut state = self.state.lock();
+
+		if now.duration_since(state.last_sweep) >= window {
+			// Buckets which haven't been touched for a window are full again
+			// and behave the same as a missing bucket.
+			state.buckets.retain(|_, bucket| now.duration_since(bucket.updated) < window);
+			state.last_sweep = now;
+		}
+
+		let bucket = state.buckets
+			.entry(key)
+			.or_insert(Bucket {
+				tokens: capacity,
+				updated: now,
+			});
+
+		let refilled = now.duration_since(bucket.updated).as_secs_f64() * rate;
+		bucket.tokens = (bucket.tokens + refilled).min(capacity);
+		bucket.updated = now;
+
+		if bucket.tokens >= 1.0 {
+			if consume {
+				bucket.tokens -= 1.0;
+			}
+			Ok(())
+		} else {
+			Err(RateLimited {
+				retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / rate),
+			})
+		}
+	}
+}
+
+/// Count a request from a user against a limit. If `consume` is false, the
+/// request is only checked against the limit without counting it.
+pub fn limit(
+	limiter: &RateLimiter,
+	authed: &AuthedUser,
+	address: Option<SocketAddr>,
+	consume: bool,
+) -> Result<(), RateLimited> {
+	let id = Option::<&User>::from(authed).and_then(|user| user.id.clone());
+
+	let key = match (id, address) {
+		(Some(id), _) => Key::User(id),
+		(None, Some(address)) => Key::Address(address.ip()),
+		(None, None) => Key::Unknown,
+	};
+
+	limiter.acquire(key, consume)
+}
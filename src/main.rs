@@ -1,8 +1,9 @@
+#![recursion_limit = "256"]
+
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate diesel;
-#[macro_use]
 extern crate diesel_migrations;
 
 #[macro_use]
@@ -14,13 +15,18 @@ mod config;
 mod filters;
 mod objects;
 mod routes;
+mod webhook;
 //mod socket;
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use access::permissions::PermissionsError;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use filters::header::authorization::BearerError;
+use filters::{
+	body::patch::InvalidMergePatch,
+	header::authorization::BearerError,
+	rate_limit::RateLimited,
+};
 use futures_util::future;
 use http::{Method, StatusCode};
 //use tokio::sync::RwLock;
@@ -45,8 +51,35 @@ pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
 #[tokio::main]
 async fn main() {
-	let manager = diesel::r2d2::ConnectionManager::new(CONFIG.database_url.to_string());
-	let pool = Arc::new(r2d2::Pool::new(manager).unwrap());
+	tracing_subscriber::fmt()
+		.with_env_filter(tracing_subscriber::EnvFilter::new(&CONFIG.log_level))
+		.init();
+
+	// Fail at startup rather than on first use if these are invalid.
+	config::tunables();
+
+	// The database may still be starting alongside us, so retry with backoff.
+	let mut delay = Duration::from_secs(CONFIG.database_retry_delay);
+	let mut attempt = 1;
+	let pool = loop {
+		let manager = diesel::r2d2::ConnectionManager::new(CONFIG.database_url.to_string());
+		match r2d2::Pool::new(manager) {
+			Ok(pool) => break Arc::new(pool),
+			Err(err) if attempt < CONFIG.database_connect_attempts => {
+				tracing::warn!(
+					attempt,
+					attempts = CONFIG.database_connect_attempts,
+					retry_in = delay.as_secs(),
+					%err,
+					"failed to connect to database",
+				);
+				tokio::time::sleep(delay).await;
+				delay *= 2;
+				attempt += 1;
+			},
+			Err(err) => panic!("Failed to connect to database: {}", err),
+		}
+	};
 	let mut connection = pool.get().unwrap();
 
 	MigrationHarness::run_pending_migrations(&mut connection, MIGRATIONS)
@@ -60,6 +93,44 @@ async fn main() {
 
 	let boards: BoardDataMap = Arc::new(RwLock::new(boards));
 
+	// User counts are sent periodically rather than on every change so that
+	// busy boards don't flood sockets with updates.
+	if CONFIG.user_count_interval > 0 {
+		let boards = Arc::clone(&boards);
+		let pool = Arc::clone(&pool);
+		tokio::spawn(async move {
+			let period = Duration::from_secs(CONFIG.user_count_interval);
+			let mut interval = tokio::time::interval(period);
+			loop {
+				interval.tick().await;
+
+				let boards = Arc::clone(&boards);
+				let pool = Arc::clone(&pool);
+				// Counting queries the database and takes board locks, both of
+				// which block.
+				let update = tokio::task::spawn_blocking(move || {
+					let mut connection = match pool.get() {
+						Ok(connection) => connection,
+						Err(_) => return,
+					};
+
+					let boards = boards.read().values().cloned().collect::<Vec<_>>();
+					for board in boards {
+						if let Some(ref board) = *board.read() {
+							if let Err(err) = board.update_user_count(&mut connection) {
+								tracing::error!(board = board.id, %err, "failed to update user count");
+							}
+						}
+					}
+				});
+
+				if let Err(err) = update.await {
+					tracing::error!(%err, "failed to update user counts");
+				}
+			}
+		});
+	}
+
 	let routes = routes::core::info::get()
 		.or(routes::core::access::get())
 		.or(routes::core::boards::list(Arc::clone(&boards)))
@@ -67,7 +138,7 @@ async fn main() {
 			Arc::clone(&boards),
 			Arc::clone(&pool),
 		))
-		.or(routes::core::boards::default())
+		.or(routes::core::boards::default(Arc::clone(&boards)))
 		.or(routes::core::boards::post(
 			Arc::clone(&boards),
 			Arc::clone(&pool),
@@ -76,10 +147,30 @@ async fn main() {
 			Arc::clone(&boards),
 			Arc::clone(&pool),
 		))
+		.or(routes::core::boards::colors::get(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
+		.or(routes::core::boards::colors::histogram(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
+		.or(routes::core::boards::colors::patch(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
 		.or(routes::core::boards::delete(
 			Arc::clone(&boards),
 			Arc::clone(&pool),
 		))
+		.or(routes::core::boards::restore(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
+		.or(routes::core::boards::cooldown(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
 		.or(routes::core::boards::socket(
 			Arc::clone(&boards),
 			Arc::clone(&pool),
@@ -88,6 +179,14 @@ async fn main() {
 			Arc::clone(&boards),
 			Arc::clone(&pool),
 		))
+		.or(routes::core::boards::data::get_colors_png(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
+		.or(routes::core::boards::data::get_thumbnail(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
 		.or(routes::core::boards::data::get_initial(
 			Arc::clone(&boards),
 			Arc::clone(&pool),
@@ -100,6 +199,10 @@ async fn main() {
 			Arc::clone(&boards),
 			Arc::clone(&pool),
 		))
+		.or(routes::core::boards::data::get_density(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
 		.or(routes::core::boards::data::patch_initial(
 			Arc::clone(&boards),
 			Arc::clone(&pool),
@@ -112,6 +215,14 @@ async fn main() {
 			Arc::clone(&boards),
 			Arc::clone(&pool),
 		))
+		.or(routes::core::boards::users::leaderboard(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
+		.or(routes::core::boards::users::pixels(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
 		.or(routes::core::boards::pixels::list(
 			Arc::clone(&boards),
 			Arc::clone(&pool),
@@ -120,20 +231,44 @@ async fn main() {
 			Arc::clone(&boards),
 			Arc::clone(&pool),
 		))
+		.or(routes::core::boards::pixels::lookup(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
 		.or(routes::core::boards::pixels::post(
 			Arc::clone(&boards),
 			Arc::clone(&pool),
 		))
+		.or(routes::core::boards::pixels::placeable(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
+		.or(routes::core::boards::pixels::delete(
+			Arc::clone(&boards),
+			Arc::clone(&pool),
+		))
 		.or(routes::auth::auth::get())
+		.or(routes::admin::reload_config())
+		.or(routes::admin::sectors(Arc::clone(&boards)))
 		.recover(|rejection: Rejection| {
-			if let Some(err) = rejection.find::<BearerError>() {
+			if rejection.find::<BearerError>().is_some() {
 				future::ok(StatusCode::UNAUTHORIZED.into_response())
-			} else if let Some(err) = rejection.find::<PermissionsError>() {
+			} else if rejection.find::<PermissionsError>().is_some() {
 				future::ok(StatusCode::FORBIDDEN.into_response())
+			} else if let Some(err) = rejection.find::<RateLimited>() {
+				future::ok(err.into_response())
+			} else if let Some(err) = rejection.find::<InvalidMergePatch>() {
+				future::ok(err.into_response())
+			} else if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+				future::ok(StatusCode::PAYLOAD_TOO_LARGE.into_response())
+			} else if let Some(err) = rejection.find::<warp::reject::InvalidQuery>() {
+				// malformed pagination tokens and limits end up here
+				future::ok(warp::reply::with_status(err.to_string(), StatusCode::BAD_REQUEST).into_response())
 			} else {
 				future::err(rejection)
 			}
 		})
+		.with(warp::trace::request())
 		.with(
 			warp::cors::cors()
 				.allow_any_origin()
@@ -143,7 +278,12 @@ async fn main() {
 					Method::POST,
 					Method::DELETE,
 					Method::PATCH,
-				]), // TODO: allow headers
+				]) // TODO: allow headers
+				.expose_headers([
+					"pxls-pixels-available",
+					"pxls-next-available",
+					"pxls-recent-placements",
+				]),
 		);
 
 	// Temporary fix for gzip until https://github.com/seanmonstar/warp/pull/513
@@ -152,7 +292,12 @@ async fn main() {
 		.and(routes.clone())
 		.with(warp::compression::gzip());
 
-	warp::serve(gzip_routes.or(routes))
+	// Probes are kept outside of the CORS wrapper since they're only meant
+	// for orchestrators and don't need authorization.
+	let probes = routes::health::health()
+		.or(routes::health::ready(Arc::clone(&pool)));
+
+	warp::serve(probes.or(gzip_routes).or(routes))
 		.run(([127, 0, 0, 1], CONFIG.port))
 		.await;
 }
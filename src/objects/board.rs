@@ -1,19 +1,26 @@
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{BTreeMap, HashMap, HashSet},
 	convert::TryFrom,
-	io::{Seek, SeekFrom, Write},
-	sync::{Arc, RwLock, Weak},
+	fmt,
+	fs,
+	io::{self, Read, Seek, SeekFrom, Write},
+	ops::Range,
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicU32, AtomicU64, Ordering},
+		Arc, Mutex, RwLock, Weak,
+	},
 	time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use bytes::BufMut;
 use diesel::{prelude::*, Connection as DConnection, sql_types::Record};
 use enum_map::EnumMap;
+use flate2::{write::GzEncoder, Compression};
 use http::{
 	header::{HeaderName, HeaderValue},
 	StatusCode, Uri,
 };
-use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
@@ -21,14 +28,32 @@ use tokio_util::sync::CancellationToken;
 use warp::{reject::Reject, reply::Response, Reply};
 
 use crate::{
+	access::permissions::Permission,
+	config::{self, CONFIG},
 	database::{model, schema, Connection},
 	filters::body::patch::BinaryPatch,
 	objects::{
-		packet, AuthedSocket, AuthedUser, Color, Extension, Palette, Reference, SectorBuffer,
-		SectorCache, SectorCacheAccess, Shape, User, UserCount, VecShape, color::replace_palette,
+		packet, AuthedSocket, AuthedUser, BoardSector, Color, DatedPlacement, TimestampOptions, Extension, Palette, PlacementFilter, Reference,
+		SectorBuffer, SectorCache, SectorCacheAccess, SectorLayout, Shape, User, UserCount,
+		VecShape,
+		color::{self, replace_palette, PaletteEntries, PaletteError, PalettePatch},
+		sector_cache::Len,
 	},
 };
 
+/// Board info with its times optionally also given as RFC 3339 dates.
+#[derive(Serialize)]
+pub struct DatedBoardInfo<'l> {
+	#[serde(flatten)]
+	info: &'l BoardInfo,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	created_at_rfc3339: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	updated_at_rfc3339: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	last_placement_at_rfc3339: Option<String>,
+}
+
 #[derive(Serialize, Debug)]
 pub struct BoardInfo {
 	name: String,
@@ -36,22 +61,554 @@ pub struct BoardInfo {
 	shape: VecShape,
 	palette: Palette,
 	max_pixels_available: u32,
+	visibility: Visibility,
+	/// The most pixels each user can place on the board, if limited.
+	placement_cap: Option<u32>,
+	/// The time (in seconds) for each pixel to become available on this board,
+	/// overriding the configured cooldown.
+	cooldown: Option<u32>,
+	/// Whether placing is disabled. The board can still be viewed.
+	frozen: bool,
+	/// When the board info was last changed.
+	updated_at: u64,
+	/// When the most recent placement was made, or zero if there are none.
+	#[serde(serialize_with = "serialize_last_placement_at")]
+	last_placement_at: AtomicU64,
+}
+
+fn serialize_last_placement_at<S>(
+	last_placement_at: &AtomicU64,
+	serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+	S: serde::Serializer,
+{
+	let last_placement_at = last_placement_at.load(Ordering::Relaxed);
+	Some(last_placement_at)
+		.filter(|time| *time != 0)
+		.serialize(serializer)
+}
+
+impl BoardInfo {
+	/// The info with its times formatted as requested.
+	pub fn dated(
+		&self,
+		options: TimestampOptions,
+	) -> DatedBoardInfo<'_> {
+		let time = |seconds: u64| options.format(UNIX_EPOCH + Duration::from_secs(seconds));
+		let last_placement_at = self.last_placement_at.load(Ordering::Relaxed);
+
+		DatedBoardInfo {
+			info: self,
+			created_at_rfc3339: time(self.created_at),
+			updated_at_rfc3339: time(self.updated_at),
+			last_placement_at_rfc3339: (last_placement_at != 0)
+				.then(|| time(last_placement_at))
+				.flatten(),
+		}
+	}
+}
+
+fn unix_time() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap()
+		.as_secs()
+}
+
+/// Encode an indexed PNG using the colors of a palette.
+/// The image is written a row at a time, with `read_row` filling in the
+/// indices of each row in order, so that large boards don't need a full
+/// decompressed copy in memory.
+fn encode_png(
+	width: usize,
+	height: usize,
+	palette: &Palette,
+	mut read_row: impl FnMut(usize, &mut [u8]) -> io::Result<()>,
+) -> Result<Vec<u8>, png::EncodingError> {
+	let mut colors = vec![0; 256 * 3];
+	let mut transparency = vec![0; 256];
+
+	for (index, Color { value, .. }) in palette {
+		if let Ok(index) = u8::try_from(*index) {
+			let index = usize::from(index);
+			let [red, green, blue, alpha] = value.to_be_bytes();
+			colors[index * 3..index * 3 + 3].copy_from_slice(&[red, green, blue]);
+			transparency[index] = alpha;
+		}
+	}
+
+	let mut png_data = Vec::new();
+	let mut encoder = png::Encoder::new(&mut png_data, width as u32, height as u32);
+	encoder.set_color(png::ColorType::Indexed);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.set_palette(colors);
+	encoder.set_trns(transparency);
+
+	let mut writer = encoder.write_header()?;
+	let mut image = writer.stream_writer()?;
+
+	let mut row = vec![0; width];
+	for y in 0..height {
+		read_row(y, &mut row)?;
+		image.write_all(&row)?;
+	}
+
+	image.finish()?;
+	writer.finish()?;
+
+	Ok(png_data)
+}
+
+/// The number of times each color index occurs in the first `len` bytes of
+/// `colors`, reading `chunk_size` bytes at a time.
+fn count_colors(
+	colors: &mut impl Read,
+	len: usize,
+	chunk_size: usize,
+) -> io::Result<[usize; 256]> {
+	let mut chunk = vec![0; chunk_size.max(1)];
+	let mut remaining = len;
+	let mut counts = [0; 256];
+
+	while remaining > 0 {
+		let take = remaining.min(chunk.len());
+		let read = &mut chunk[..take];
+		colors.read_exact(read)?;
+		for color in read.iter() {
+			counts[*color as usize] += 1;
+		}
+		remaining -= take;
+	}
+
+	Ok(counts)
+}
+
+/// Who can see a board.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+	/// Listed and accessible to anyone.
+	#[default]
+	Public,
+	/// Accessible to anyone, but only listed for users who can see private
+	/// boards.
+	Unlisted,
+	/// Listed and accessible only to users who can see private boards.
+	Private,
+}
+
+impl Visibility {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Public => "public",
+			Self::Unlisted => "unlisted",
+			Self::Private => "private",
+		}
+	}
+
+	fn from_str(value: &str) -> Self {
+		match value {
+			"unlisted" => Self::Unlisted,
+			"private" => Self::Private,
+			// NOTE: unknown values are treated as public since that is the
+			// column default.
+			_ => Self::Public,
+		}
+	}
+
+	/// Whether a user can access boards with this visibility.
+	fn visible_to(
+		&self,
+		user: &AuthedUser,
+	) -> bool {
+		*self != Self::Private || can_see_private(user)
+	}
+
+	/// Whether boards with this visibility should appear in a user's board
+	/// list.
+	fn listed_for(
+		&self,
+		user: &AuthedUser,
+	) -> bool {
+		*self == Self::Public || can_see_private(user)
+	}
+}
+
+fn can_see_private(user: &AuthedUser) -> bool {
+	match Option::<&User>::from(user) {
+		Some(user) => user.permissions.contains(&Permission::BoardsPrivate),
+		// anonymous users have the default permissions
+		None => User::default().permissions.contains(&Permission::BoardsPrivate),
+	}
 }
 
 #[derive(Deserialize, Debug)]
 pub struct BoardInfoPost {
 	name: String,
 	shape: VecShape,
-	palette: Palette,
+	palette: PaletteEntries,
 	max_pixels_available: u32,
+	#[serde(default)]
+	visibility: Visibility,
+	#[serde(default)]
+	placement_cap: Option<u32>,
+	#[serde(default)]
+	cooldown: Option<u32>,
+	#[serde(default)]
+	frozen: bool,
 }
 
+impl BoardInfoPost {
+	/// Check that no palette index is given more than once.
+	pub fn check_color_indices(&self) -> Result<(), DuplicateColorIndices> {
+		check_color_indices(&self.palette)
+	}
+
+	fn validate(&self) -> Result<(), InvalidField> {
+		validate_palette(&self.palette.to_palette(), CONFIG.max_palette_size)?;
+		validate_shape(&self.shape, CONFIG.max_board_pixels)?;
+		validate_max_pixels_available(self.max_pixels_available)?;
+		if let Some(placement_cap) = self.placement_cap {
+			validate_placement_cap(placement_cap)?;
+		}
+		if let Some(cooldown) = self.cooldown {
+			validate_cooldown(cooldown, self.max_pixels_available)?;
+		}
+		Ok(())
+	}
+}
+
+/// A JSON Merge Patch of a board's info.
+///
+/// Absent fields are left unchanged. Aside from the placement cap and cooldown,
+/// every field is required on a board so none can be removed; a `null` member
+/// is treated the same as an absent one.
 #[derive(Deserialize, Debug)]
 pub struct BoardInfoPatch {
 	name: Option<String>,
 	shape: Option<VecShape>,
-	palette: Option<Palette>,
+	palette: Option<PaletteEntries>,
 	max_pixels_available: Option<u32>,
+	visibility: Option<Visibility>,
+	/// Unlike other fields, null removes the cap rather than leaving it as is.
+	#[serde(default, with = "serde_with::rust::double_option")]
+	placement_cap: Option<Option<u32>>,
+	/// Null removes the override.
+	#[serde(default, with = "serde_with::rust::double_option")]
+	cooldown: Option<Option<u32>>,
+	frozen: Option<bool>,
+}
+
+impl BoardInfoPatch {
+	/// Check that no palette index is given more than once.
+	pub fn check_color_indices(&self) -> Result<(), DuplicateColorIndices> {
+		match self.palette {
+			Some(ref palette) => check_color_indices(palette),
+			None => Ok(()),
+		}
+	}
+
+	fn is_empty(&self) -> bool {
+		self.name.is_none()
+			&& self.shape.is_none()
+			&& self.palette.is_none()
+			&& self.max_pixels_available.is_none()
+			&& self.visibility.is_none()
+			&& self.placement_cap.is_none()
+			&& self.cooldown.is_none()
+			&& self.frozen.is_none()
+	}
+
+	/// Check the patch against `current`, the info it will be applied to.
+	fn validate(
+		&self,
+		current: &BoardInfo,
+	) -> Result<(), InvalidField> {
+		if let Some(ref palette) = self.palette {
+			validate_palette(&palette.to_palette(), CONFIG.max_palette_size)?;
+		}
+		if let Some(ref shape) = self.shape {
+			validate_shape(shape, CONFIG.max_board_pixels)?;
+		}
+		if let Some(max_pixels_available) = self.max_pixels_available {
+			validate_max_pixels_available(max_pixels_available)?;
+		}
+		if let Some(Some(placement_cap)) = self.placement_cap {
+			validate_placement_cap(placement_cap)?;
+		}
+		// either may come from the current info, but they're checked together
+		let cooldown = self.cooldown.unwrap_or(current.cooldown);
+		if let Some(cooldown) = cooldown {
+			let max_pixels_available = self
+				.max_pixels_available
+				.unwrap_or(current.max_pixels_available);
+			validate_cooldown(cooldown, max_pixels_available)?;
+		}
+		Ok(())
+	}
+}
+
+fn check_color_indices(palette: &PaletteEntries) -> Result<(), DuplicateColorIndices> {
+	let duplicates = palette.duplicate_indices();
+	if duplicates.is_empty() {
+		Ok(())
+	} else {
+		Err(DuplicateColorIndices(duplicates))
+	}
+}
+
+fn validate_palette(
+	palette: &Palette,
+	max_size: usize,
+) -> Result<(), InvalidField> {
+	let invalid_multiplier = palette
+		.values()
+		.any(|color| !color.cooldown_multiplier.is_finite() || color.cooldown_multiplier <= 0.0);
+
+	if palette.is_empty() {
+		Err(InvalidField::EmptyPalette)
+	} else if palette.len() > max_size {
+		Err(InvalidField::PaletteTooLarge)
+	} else if palette.keys().any(|&index| index > u32::from(u8::MAX)) {
+		Err(InvalidField::ColorIndexOutOfRange)
+	} else if !color::duplicate_values(palette).is_empty() {
+		Err(InvalidField::DuplicateColorValues)
+	} else if invalid_multiplier {
+		Err(InvalidField::InvalidCooldownMultiplier)
+	} else {
+		Ok(())
+	}
+}
+
+/// The time (in seconds) after placing until each of `slots` pixels becomes
+/// available again, given the density of the last placement's position.
+/// `cooldown` overrides the configured cooldown for every slot.
+fn cooldown_offsets(
+	tunables: &config::Tunables,
+	cooldown: Option<u32>,
+	density: usize,
+	slots: usize,
+) -> Vec<u32> {
+	// Placing over heavily contested pixels costs more.
+	let density_penalty = u32::try_from(density)
+		.unwrap_or(u32::MAX)
+		.saturating_mul(tunables.cooldown_density_penalty);
+
+	// NOTE: saturating since configured cooldowns aren't validated like
+	// board ones, and a pixel which never comes back is the best we can do.
+	(0..slots)
+		.map(|slot| {
+			let cooldown = cooldown.unwrap_or_else(|| tunables.slot_cooldown(slot));
+			cooldown.saturating_add(density_penalty)
+		})
+		.scan(0_u32, |total, cooldown| {
+			*total = total.saturating_add(cooldown);
+			Some(*total)
+		})
+		.collect()
+}
+
+/// `palette` with each entry of `patch` applied.
+fn patched_palette(
+	palette: &Palette,
+	patch: &PalettePatch,
+) -> Result<Palette, InvalidField> {
+	let mut palette = palette.clone();
+	for (index, color_patch) in patch {
+		match color_patch {
+			Some(color_patch) => {
+				let color = color_patch
+					.apply(palette.get(index))
+					.ok_or(InvalidField::IncompleteColor)?;
+				palette.insert(*index, color);
+			},
+			None => {
+				palette.remove(index);
+			},
+		}
+	}
+
+	Ok(palette)
+}
+
+fn validate_shape(
+	shape: &VecShape,
+	max_pixels: usize,
+) -> Result<(), InvalidField> {
+	let degenerate = shape.is_empty()
+		|| shape.iter().any(|level| level.is_empty() || level.contains(&0));
+
+	// computed without overflow since the product could be enormous
+	let total_size = shape
+		.iter()
+		.flatten()
+		.try_fold(1_usize, |total, size| total.checked_mul(*size));
+
+	// NOTE: the size must be checked before sector_size is used since that
+	// multiplies without checking.
+	if degenerate {
+		Err(InvalidField::DegenerateShape)
+	} else if total_size.is_none_or(|total_size| total_size > max_pixels) {
+		Err(InvalidField::ShapeTooLarge)
+	} else if shape.sector_size() == 0 {
+		Err(InvalidField::DegenerateShape)
+	} else {
+		Ok(())
+	}
+}
+
+fn validate_placement_cap(placement_cap: u32) -> Result<(), InvalidField> {
+	if placement_cap == 0 {
+		Err(InvalidField::NoPlacementsAllowed)
+	} else {
+		Ok(())
+	}
+}
+
+/// Every pixel's cooldown is added up from the last placement and stored as a
+/// board timestamp, so the cooldowns of all pixels together must fit in one.
+fn validate_cooldown(
+	cooldown: u32,
+	max_pixels_available: u32,
+) -> Result<(), InvalidField> {
+	let total = u64::from(cooldown) * u64::from(max_pixels_available);
+	if total > i32::MAX as u64 {
+		Err(InvalidField::CooldownTooLong)
+	} else {
+		Ok(())
+	}
+}
+
+fn validate_max_pixels_available(max_pixels_available: u32) -> Result<(), InvalidField> {
+	if max_pixels_available == 0 {
+		Err(InvalidField::NoPixelsAvailable)
+	} else {
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum InvalidField {
+	EmptyPalette,
+	PaletteTooLarge,
+	ColorIndexOutOfRange,
+	DuplicateColorValues,
+	InvalidCooldownMultiplier,
+	IncompleteColor,
+	RemapColor,
+	DegenerateShape,
+	ShapeTooLarge,
+	NoPixelsAvailable,
+	NoPlacementsAllowed,
+	CooldownTooLong,
+}
+
+impl InvalidField {
+	fn field(&self) -> &'static str {
+		match self {
+			Self::EmptyPalette => "palette",
+			Self::PaletteTooLarge => "palette",
+			Self::ColorIndexOutOfRange => "palette",
+			Self::DuplicateColorValues => "palette",
+			Self::InvalidCooldownMultiplier => "palette",
+			Self::IncompleteColor => "palette",
+			Self::RemapColor => "remap_to",
+			Self::DegenerateShape => "shape",
+			Self::ShapeTooLarge => "shape",
+			Self::NoPixelsAvailable => "max_pixels_available",
+			Self::NoPlacementsAllowed => "placement_cap",
+			Self::CooldownTooLong => "cooldown",
+		}
+	}
+
+	fn reason(&self) -> &'static str {
+		match self {
+			Self::EmptyPalette => "palette must contain at least one color",
+			Self::PaletteTooLarge => "palette contains more colors than the server allows",
+			Self::ColorIndexOutOfRange => "palette indices must be between 0 and 255",
+			Self::DuplicateColorValues => "palette must not contain the same color value twice",
+			Self::InvalidCooldownMultiplier => "cooldown multipliers must be finite and positive",
+			Self::IncompleteColor => "new colors must have a name and value",
+			Self::RemapColor => "remap_to must be a color in the resulting palette",
+			Self::DegenerateShape => "shape must have at least one level and no zero-sized dimensions",
+			Self::ShapeTooLarge => "shape contains more pixels than the server allows",
+			Self::NoPixelsAvailable => "max_pixels_available must be at least 1",
+			Self::NoPlacementsAllowed => "placement_cap must be at least 1",
+			Self::CooldownTooLong => "cooldown is too long for max_pixels_available",
+		}
+	}
+}
+
+impl Reply for InvalidField {
+	fn into_response(self) -> Response {
+		#[derive(Serialize)]
+		struct Invalid {
+			field: &'static str,
+			reason: &'static str,
+		}
+
+		warp::reply::with_status(
+			warp::reply::json(&Invalid {
+				field: self.field(),
+				reason: self.reason(),
+			}),
+			StatusCode::UNPROCESSABLE_ENTITY,
+		)
+		.into_response()
+	}
+}
+
+/// Palette indices which were given more than once in a request.
+#[derive(Debug)]
+pub struct DuplicateColorIndices(Vec<u32>);
+
+impl Reply for DuplicateColorIndices {
+	fn into_response(self) -> Response {
+		#[derive(Serialize)]
+		struct Invalid {
+			field: &'static str,
+			reason: &'static str,
+			indices: Vec<u32>,
+		}
+
+		warp::reply::with_status(
+			warp::reply::json(&Invalid {
+				field: "palette",
+				reason: "palette must not contain the same index twice",
+				indices: self.0,
+			}),
+			StatusCode::UNPROCESSABLE_ENTITY,
+		)
+		.into_response()
+	}
+}
+
+#[derive(Debug)]
+pub enum CreateError {
+	Invalid(InvalidField),
+	DatabaseError(diesel::result::Error),
+}
+
+impl From<InvalidField> for CreateError {
+	fn from(error: InvalidField) -> Self {
+		Self::Invalid(error)
+	}
+}
+
+impl From<diesel::result::Error> for CreateError {
+	fn from(error: diesel::result::Error) -> Self {
+		Self::DatabaseError(error)
+	}
+}
+
+impl Reply for CreateError {
+	fn into_response(self) -> Response {
+		match self {
+			Self::Invalid(invalid) => invalid.into_response(),
+			Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		}
+	}
 }
 
 impl From<BoardInfoPatch> for packet::server::BoardInfo {
@@ -61,13 +618,21 @@ impl From<BoardInfoPatch> for packet::server::BoardInfo {
 			shape,
 			palette,
 			max_pixels_available,
+			visibility,
+			placement_cap,
+			cooldown,
+			frozen,
 		}: BoardInfoPatch
 	) -> Self {
 		Self {
 			name,
 			shape,
-			palette,
+			palette: palette.map(|palette| palette.to_palette()),
 			max_pixels_available,
+			visibility,
+			placement_cap,
+			cooldown,
+			frozen,
 		}
 	}
 }
@@ -79,6 +644,8 @@ struct UserConnections {
 }
 
 impl UserConnections {
+	// sockets hash by their uuid, which never changes
+	#[allow(clippy::mutable_key_type)]
 	fn new(
 		socket: Arc<AuthedSocket>,
 		cooldown_info: CooldownInfo,
@@ -217,16 +784,21 @@ impl UserConnections {
 pub struct Connections {
 	by_uid: HashMap<String, Arc<RwLock<UserConnections>>>,
 	by_extension: EnumMap<Extension, HashSet<Arc<AuthedSocket>>>,
+	sockets: HashSet<Arc<AuthedSocket>>,
 }
 
 impl Connections {
+	pub fn len(&self) -> usize {
+		self.sockets.len()
+	}
+
 	pub fn insert(
 		&mut self,
 		socket: Arc<AuthedSocket>,
 		cooldown_info: Option<CooldownInfo>,
 	) {
 		let user = socket.user.read();
-		if let AuthedUser::Authed { user, valid_until } = &*user {
+		if let AuthedUser::Authed { user, .. } = &*user {
 			if let Some(ref id) = user.id {
 				self.by_uid
 					.entry(id.clone())
@@ -245,6 +817,8 @@ impl Connections {
 		for extension in socket.extensions {
 			self.by_extension[extension].insert(Arc::clone(&socket));
 		}
+
+		self.sockets.insert(Arc::clone(&socket));
 	}
 
 	pub fn remove(
@@ -252,7 +826,7 @@ impl Connections {
 		socket: Arc<AuthedSocket>,
 	) {
 		let user = socket.user.read();
-		if let AuthedUser::Authed { user, valid_until } = &*user {
+		if let AuthedUser::Authed { user, .. } = &*user {
 			if let Some(ref id) = user.id {
 				let connections = self.by_uid.get(id).unwrap();
 				let mut connections = connections.write().unwrap();
@@ -269,6 +843,8 @@ impl Connections {
 		for extension in socket.extensions {
 			self.by_extension[extension].remove(&socket);
 		}
+
+		self.sockets.remove(&socket);
 	}
 
 	pub fn send(
@@ -276,22 +852,42 @@ impl Connections {
 		packet: packet::server::Packet,
 	) {
 		let extension = Extension::from(&packet);
-		for connection in self.by_extension[extension].iter() {
-			connection.send(&packet);
+
+		// Board info is needed to make sense of anything else about the board,
+		// so it goes to every socket regardless of extensions.
+		let info_only = match packet {
+			packet::server::Packet::BoardUpdate { info: Some(ref info), .. } => {
+				Some(packet::server::Packet::BoardUpdate {
+					info: Some(info.clone()),
+					data: None,
+				})
+			},
+			_ => None,
+		};
+
+		for connection in self.sockets.iter() {
+			let packet = if connection.extensions.contains(extension) {
+				&packet
+			} else if let Some(ref info_only) = info_only {
+				info_only
+			} else {
+				continue;
+			};
+
+			match connection.region {
+				Some(ref region) => {
+					if let Some(packet) = packet.within(region) {
+						connection.send(&packet);
+					}
+				},
+				None => connection.send(packet),
+			}
 		}
 	}
 
-	pub fn send_to_user(
-		&self,
-		user_id: String,
-		packet: packet::server::Packet,
-	) {
-		if let Some(connections) = self.by_uid.get(&user_id) {
-			connections
-				.read()
-				.unwrap()
-				.send(&packet);
-		}
+	/// The ids of users with sockets connected.
+	pub fn user_ids(&self) -> Vec<String> {
+		self.by_uid.keys().cloned().collect()
 	}
 
 	pub fn set_user_cooldown(
@@ -307,10 +903,8 @@ impl Connections {
 	pub fn close(&mut self) {
 		// TODO: maybe send a close reason
 
-		for connections in self.by_extension.values() {
-			for connection in connections {
-				connection.close();
-			}
+		for connection in self.sockets.iter() {
+			connection.close();
 		}
 	}
 }
@@ -319,13 +913,65 @@ pub struct Board {
 	pub id: i32,
 	pub info: BoardInfo,
 	connections: Connections,
+	layout: SectorLayout,
 	sectors: SectorCache,
+	/// The active user count most recently sent to sockets.
+	last_user_count: Mutex<Option<usize>>,
+	thumbnail: Mutex<Option<Thumbnail>>,
+	data_version: DataVersion,
+	/// The latest timestamp at which placements were removed outright.
+	/// Replays can't reconstruct removals so they must refetch past this.
+	cleared_at: AtomicU32,
+}
+
+/// Tracks changes to any of a board's data buffers for cache validation.
+struct DataVersion {
+	/// When (in milliseconds) the board was loaded, so that change counts
+	/// from before a restart aren't mistaken for current ones.
+	loaded_at: u128,
+	changes: AtomicU64,
+	/// When the data last changed.
+	modified_at: AtomicU64,
+}
+
+impl DataVersion {
+	fn new() -> Self {
+		let now = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap();
+
+		Self {
+			loaded_at: now.as_millis(),
+			changes: AtomicU64::new(0),
+			// NOTE: changes made before loading aren't known so the data is
+			// assumed to have changed just now.
+			modified_at: AtomicU64::new(now.as_secs()),
+		}
+	}
+
+	fn bump(&self) {
+		self.changes.fetch_add(1, Ordering::Relaxed);
+		self.modified_at.store(unix_time(), Ordering::Relaxed);
+	}
+}
+
+/// The longest side (in pixels) of a board thumbnail.
+const THUMBNAIL_SIZE: usize = 256;
+
+struct Thumbnail {
+	/// None if the board can't be represented as an image.
+	png: Option<Vec<u8>>,
+	rendered_at: Instant,
+	/// Whether the board has changed since rendering.
+	stale: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct CooldownInfo {
 	cooldowns: Vec<SystemTime>,
 	pub pixels_available: usize,
+	/// Placements made within the idle timeout.
+	pub recent_placements: usize,
 }
 
 impl CooldownInfo {
@@ -344,14 +990,21 @@ impl CooldownInfo {
 		Self {
 			cooldowns,
 			pixels_available,
+			recent_placements: 0,
 		}
 	}
 
 	pub fn into_headers(self) -> Vec<(HeaderName, HeaderValue)> {
-		let mut headers = vec![(
-			HeaderName::from_static("pxls-pixels-available"),
-			self.pixels_available.into(),
-		)];
+		let mut headers = vec![
+			(
+				HeaderName::from_static("pxls-pixels-available"),
+				self.pixels_available.into(),
+			),
+			(
+				HeaderName::from_static("pxls-recent-placements"),
+				self.recent_placements.into(),
+			),
+		];
 
 		if let Some(next_available) = self
 			.cooldowns
@@ -373,7 +1026,14 @@ impl CooldownInfo {
 	pub fn cooldown(&self) -> Option<SystemTime> {
 		self.cooldowns
 			.get(self.pixels_available)
-			.map(SystemTime::clone)
+			.copied()
+	}
+
+	/// When each pixel not yet available will become available, in order.
+	pub fn schedule(&self) -> &[SystemTime] {
+		self.cooldowns
+			.get(self.pixels_available..)
+			.unwrap_or(&[])
 	}
 }
 
@@ -389,13 +1049,143 @@ impl Iterator for CooldownInfo {
 	}
 }
 
-#[derive(FromPrimitive)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum MaskValue {
 	NoPlace = 0,
 	Place = 1,
 	Adjacent = 2,
 }
 
+impl FromPrimitive for MaskValue {
+	fn from_i64(n: i64) -> Option<Self> {
+		u64::try_from(n).ok().and_then(Self::from_u64)
+	}
+
+	fn from_u64(n: u64) -> Option<Self> {
+		match n {
+			0 => Some(Self::NoPlace),
+			1 => Some(Self::Place),
+			2 => Some(Self::Adjacent),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ShapeChange {
+	/// Existing data was moved to the equivalent positions in the new shape.
+	Preserved,
+	/// The new shape could not hold the existing data so it was discarded.
+	Discarded,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+	Io(io::Error),
+	DatabaseError(diesel::result::Error),
+}
+
+impl From<io::Error> for BackupError {
+	fn from(error: io::Error) -> Self {
+		Self::Io(error)
+	}
+}
+
+impl From<diesel::result::Error> for BackupError {
+	fn from(error: diesel::result::Error) -> Self {
+		Self::DatabaseError(error)
+	}
+}
+
+impl fmt::Display for BackupError {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "{}", error),
+			Self::DatabaseError(error) => write!(f, "{}", error),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum UpdateError {
+	Invalid(InvalidField),
+	ColorsInUse(Vec<u32>),
+	BackupFailed(BackupError),
+	DatabaseError(diesel::result::Error),
+	/// Existing board data couldn't be read.
+	DataError(io::Error),
+}
+
+impl From<BackupError> for UpdateError {
+	fn from(error: BackupError) -> Self {
+		Self::BackupFailed(error)
+	}
+}
+
+impl From<InvalidField> for UpdateError {
+	fn from(error: InvalidField) -> Self {
+		Self::Invalid(error)
+	}
+}
+
+impl From<diesel::result::Error> for UpdateError {
+	fn from(error: diesel::result::Error) -> Self {
+		Self::DatabaseError(error)
+	}
+}
+
+impl From<io::Error> for UpdateError {
+	fn from(error: io::Error) -> Self {
+		Self::DataError(error)
+	}
+}
+
+impl From<PaletteError> for UpdateError {
+	fn from(error: PaletteError) -> Self {
+		match error {
+			PaletteError::ColorsInUse(colors) => Self::ColorsInUse(colors),
+			PaletteError::DatabaseError(error) => Self::DatabaseError(error),
+		}
+	}
+}
+
+impl Reply for UpdateError {
+	fn into_response(self) -> Response {
+		match self {
+			Self::Invalid(invalid) => invalid.into_response(),
+			Self::ColorsInUse(colors) => {
+				#[derive(Serialize)]
+				struct ColorsInUse {
+					colors_in_use: Vec<u32>,
+				}
+
+				warp::reply::with_status(
+					warp::reply::json(&ColorsInUse { colors_in_use: colors }),
+					StatusCode::CONFLICT,
+				)
+				.into_response()
+			},
+			Self::BackupFailed(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			Self::DataError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+		}
+	}
+}
+
+/// Whether a position could currently be placed at.
+#[derive(Serialize, Debug)]
+pub struct Placeability {
+	mask: MaskValue,
+	placeable: bool,
+	/// The error a placement would be rejected with, if any.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	reason: Option<&'static str>,
+}
+
 #[derive(Debug)]
 pub enum PlaceError {
 	UnknownMaskValue,
@@ -403,21 +1193,62 @@ pub enum PlaceError {
 	InvalidColor,
 	NoOp,
 	Cooldown,
+	CapReached,
+	Frozen,
 	OutOfBounds,
+	DatabaseError(diesel::result::Error),
 }
 
 impl Reject for PlaceError {}
 
-impl Reply for PlaceError {
-	fn into_response(self) -> Response {
+impl From<diesel::result::Error> for PlaceError {
+	fn from(error: diesel::result::Error) -> Self {
+		Self::DatabaseError(error)
+	}
+}
+
+impl PlaceError {
+	/// A machine-readable identifier for the error.
+	pub fn code(&self) -> &'static str {
+		match self {
+			Self::UnknownMaskValue => "unknown_mask_value",
+			Self::Unplacable => "unplacable",
+			Self::InvalidColor => "invalid_color",
+			Self::NoOp => "no_op",
+			Self::Cooldown => "cooldown",
+			Self::CapReached => "cap_reached",
+			Self::Frozen => "frozen",
+			Self::OutOfBounds => "out_of_bounds",
+			Self::DatabaseError(_) => "database_error",
+		}
+	}
+
+	fn status(&self) -> StatusCode {
 		match self {
 			Self::UnknownMaskValue => StatusCode::INTERNAL_SERVER_ERROR,
 			Self::Unplacable => StatusCode::FORBIDDEN,
 			Self::InvalidColor => StatusCode::UNPROCESSABLE_ENTITY,
 			Self::NoOp => StatusCode::CONFLICT,
 			Self::Cooldown => StatusCode::TOO_MANY_REQUESTS,
+			Self::CapReached => StatusCode::FORBIDDEN,
+			Self::Frozen => StatusCode::FORBIDDEN,
 			Self::OutOfBounds => StatusCode::NOT_FOUND,
+			Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
 		}
+	}
+}
+
+impl Reply for PlaceError {
+	fn into_response(self) -> Response {
+		#[derive(Serialize)]
+		struct Error {
+			code: &'static str,
+		}
+
+		warp::reply::with_status(
+			warp::reply::json(&Error { code: self.code() }),
+			self.status(),
+		)
 		.into_response()
 	}
 }
@@ -426,11 +1257,11 @@ impl Board {
 	pub fn create(
 		info: BoardInfoPost,
 		connection: &mut Connection,
-	) -> QueryResult<Self> {
-		let now = SystemTime::now()
-			.duration_since(UNIX_EPOCH)
-			.unwrap()
-			.as_secs();
+	) -> Result<Self, CreateError> {
+		info.validate()?;
+
+		let now = unix_time();
+		let layout = SectorLayout::new(&info.shape, CONFIG.sector_size);
 
 		let new_board = diesel::insert_into(schema::board::table)
 			.values(model::NewBoard {
@@ -438,12 +1269,80 @@ impl Board {
 				created_at: now as i64,
 				shape: info.shape.into(),
 				max_stacked: info.max_pixels_available as i32,
+				visibility: info.visibility.as_str().to_owned(),
+				updated_at: now as i64,
+				placement_cap: info.placement_cap.map(|cap| cap as i32),
+				cooldown: info.cooldown.map(|cooldown| cooldown as i32),
+				frozen: info.frozen,
+				sector_size: Some(layout.size as i32),
 			})
 			.get_result::<model::Board>(connection)?;
 
-		crate::objects::color::replace_palette(&info.palette, new_board.id, connection)?;
+		replace_palette(&info.palette.to_palette(), new_board.id, connection)
+			.map_err(|error| {
+				match error {
+					PaletteError::DatabaseError(error) => error,
+					PaletteError::ColorsInUse(_) => unreachable!("new boards have no placements"),
+				}
+			})?;
+
+		Ok(Self::load(new_board, connection)?)
+	}
+
+	/// Whether a user can access this board.
+	pub fn visible_to(
+		&self,
+		user: &AuthedUser,
+	) -> bool {
+		self.info.visibility.visible_to(user)
+	}
+
+	/// Whether this board should appear in a user's board list.
+	pub fn listed_for(
+		&self,
+		user: &AuthedUser,
+	) -> bool {
+		self.info.visibility.listed_for(user)
+	}
+
+	/// The positions covered by a set of sectors.
+	/// Returns None if any of the sectors don't exist.
+	pub fn sector_region(
+		&self,
+		sectors: &[usize],
+	) -> Option<Vec<Range<u64>>> {
+		let sector_count = self.layout.count;
+		let sector_size = self.layout.size as u64;
+
+		sectors
+			.iter()
+			.map(|sector| {
+				(*sector < sector_count).then(|| {
+					let start = *sector as u64 * sector_size;
+					start..start + sector_size
+				})
+			})
+			.collect()
+	}
+
+	/// Convert a board-relative timestamp to a system time.
+	pub fn time_of(
+		&self,
+		timestamp: u32,
+	) -> SystemTime {
+		UNIX_EPOCH + Duration::from_secs(self.info.created_at + u64::from(timestamp))
+	}
 
-		Self::load(new_board, connection)
+	/// A placement on this board with its time formatted as requested.
+	pub fn dated<'l>(
+		&self,
+		placement: &'l model::Placement,
+		options: TimestampOptions,
+	) -> DatedPlacement<'l> {
+		DatedPlacement {
+			placement,
+			timestamp_rfc3339: options.format(self.time_of(placement.timestamp as u32)),
+		}
 	}
 
 	pub fn read<'l>(
@@ -473,6 +1372,8 @@ impl Board {
 			.write(&patch.data)
 			.map_err(|_| "write error")?;
 
+		self.data_changed();
+
 		let packet = packet::server::Packet::BoardUpdate {
 			info: None,
 			data: Some(packet::server::BoardData {
@@ -508,6 +1409,8 @@ impl Board {
 			.write(&patch.data)
 			.map_err(|_| "write error")?;
 
+		self.data_changed();
+
 		let packet = packet::server::Packet::BoardUpdate {
 			info: None,
 			data: Some(packet::server::BoardData {
@@ -532,15 +1435,21 @@ impl Board {
 		&mut self,
 		info: BoardInfoPatch,
 		connection: &mut Connection,
-	) -> QueryResult<()> {
-		assert!(
-			info.name.is_some()
-				|| info.palette.is_some()
-				|| info.shape.is_some()
-				|| info.max_pixels_available.is_some()
-		);
+	) -> Result<Option<ShapeChange>, UpdateError> {
+		if info.is_empty() {
+			return Ok(None);
+		}
+
+		info.validate(&self.info)?;
+
+		let layout = info
+			.shape
+			.as_ref()
+			.map(|shape| SectorLayout::new(shape, CONFIG.sector_size));
+
+		let shape_change = connection.transaction::<_, UpdateError, _>(|connection| {
+			let mut shape_change = None;
 
-		connection.transaction::<_, diesel::result::Error, _>(|connection| {
 			if let Some(ref name) = info.name {
 				diesel::update(schema::board::table)
 					.set(schema::board::name.eq(name))
@@ -549,20 +1458,28 @@ impl Board {
 			}
 
 			if let Some(ref palette) = info.palette {
-				replace_palette(palette, self.id, connection)?;
+				replace_palette(&palette.to_palette(), self.id, connection)?;
 			}
 
-			if let Some(ref shape) = info.shape {
-				// TODO: try and preserve data.
-
+			if let (Some(shape), Some(layout)) = (&info.shape, layout) {
 				diesel::update(schema::board::table)
-					.set(schema::board::shape.eq(serde_json::to_value(shape).unwrap()))
+					.set((
+						schema::board::shape.eq(serde_json::Value::from(shape.clone())),
+						schema::board::sector_size.eq(layout.size as i32),
+					))
 					.filter(schema::board::id.eq(self.id))
 					.execute(connection)?;
 
-				diesel::delete(schema::board_sector::table)
-					.filter(schema::board_sector::board.eq(self.id))
-					.execute(connection)?;
+				if shape.encloses(&self.info.shape) {
+					self.reshape_data(shape, layout, connection)?;
+					shape_change = Some(ShapeChange::Preserved);
+				} else {
+					self.backup(connection)?;
+					diesel::delete(schema::board_sector::table)
+						.filter(schema::board_sector::board.eq(self.id))
+						.execute(connection)?;
+					shape_change = Some(ShapeChange::Discarded);
+				}
 			}
 
 			if let Some(max_stacked) = info.max_pixels_available {
@@ -572,31 +1489,84 @@ impl Board {
 					.execute(connection)?;
 			}
 
-			Ok(())
+			if let Some(visibility) = info.visibility {
+				diesel::update(schema::board::table)
+					.set(schema::board::visibility.eq(visibility.as_str()))
+					.filter(schema::board::id.eq(self.id))
+					.execute(connection)?;
+			}
+
+			if let Some(placement_cap) = info.placement_cap {
+				diesel::update(schema::board::table)
+					.set(schema::board::placement_cap.eq(placement_cap.map(|cap| cap as i32)))
+					.filter(schema::board::id.eq(self.id))
+					.execute(connection)?;
+			}
+
+			if let Some(cooldown) = info.cooldown {
+				diesel::update(schema::board::table)
+					.set(schema::board::cooldown.eq(cooldown.map(|cooldown| cooldown as i32)))
+					.filter(schema::board::id.eq(self.id))
+					.execute(connection)?;
+			}
+
+			if let Some(frozen) = info.frozen {
+				diesel::update(schema::board::table)
+					.set(schema::board::frozen.eq(frozen))
+					.filter(schema::board::id.eq(self.id))
+					.execute(connection)?;
+			}
+
+			self.touch(connection)?;
+
+			Ok(shape_change)
 		})?;
 
 		if let Some(ref name) = info.name {
 			self.info.name = name.clone();
 		}
 
+		// palette and shape changes both affect the data
+		let mut data_changed = false;
+
 		if let Some(ref palette) = info.palette {
-			self.info.palette = palette.clone();
+			let palette = palette.to_palette();
+			data_changed |= palette != self.info.palette;
+			self.info.palette = palette;
 		}
 
-		if let Some(ref shape) = info.shape {
+		if let (Some(shape), Some(layout)) = (&info.shape, layout) {
+			data_changed |= *shape != self.info.shape;
 			self.info.shape = shape.clone();
-
-			self.sectors = SectorCache::new(
-				self.id,
-				self.info.shape.sector_count(),
-				self.info.shape.sector_size(),
-			)
+			self.layout = layout;
+			self.sectors = SectorCache::new(self.id, layout.count, layout.size)
 		}
 
 		if let Some(max_stacked) = info.max_pixels_available {
 			self.info.max_pixels_available = max_stacked;
 		}
 
+		if let Some(visibility) = info.visibility {
+			self.info.visibility = visibility;
+		}
+
+		if let Some(placement_cap) = info.placement_cap {
+			self.info.placement_cap = placement_cap;
+		}
+
+		let cooldown_changed = info.cooldown.is_some();
+		if let Some(cooldown) = info.cooldown {
+			self.info.cooldown = cooldown;
+		}
+
+		if let Some(frozen) = info.frozen {
+			self.info.frozen = frozen;
+		}
+
+		if data_changed {
+			self.data_changed();
+		}
+
 		let packet = packet::server::Packet::BoardUpdate {
 			info: Some(info.into()),
 			data: None,
@@ -604,9 +1574,470 @@ impl Board {
 
 		self.connections.send(packet);
 
+		// NOTE: the patch is already committed, so failures here can only be
+		// logged; those users are sent their cooldown on their next change.
+		if cooldown_changed {
+			for user_id in self.connections.user_ids() {
+				let user = User::from_id(user_id.clone());
+				match self.user_cooldown_info(&user, connection) {
+					Ok(cooldown_info) => {
+						self.connections
+							.set_user_cooldown(user_id, cooldown_info);
+					},
+					Err(err) => {
+						tracing::error!(board = self.id, %err, "failed to recalculate a user's cooldown");
+					},
+				}
+			}
+		}
+
+		Ok(shape_change)
+	}
+
+	/// Record that the board info changed now.
+	fn touch(
+		&mut self,
+		connection: &mut Connection,
+	) -> QueryResult<()> {
+		let now = unix_time();
+
+		diesel::update(schema::board::table)
+			.set(schema::board::updated_at.eq(now as i64))
+			.filter(schema::board::id.eq(self.id))
+			.execute(connection)?;
+
+		self.info.updated_at = now;
+
+		Ok(())
+	}
+
+	/// Change individual palette entries, leaving the rest of the palette as
+	/// it is. If `remap_to` is set, pixels of removed colors become that color
+	/// rather than the removal failing.
+	pub fn patch_palette(
+		&mut self,
+		patch: PalettePatch,
+		remap_to: Option<u32>,
+		connection: &mut Connection,
+	) -> Result<(), UpdateError> {
+		let palette = patched_palette(&self.info.palette, &patch)?;
+
+		validate_palette(&palette, CONFIG.max_palette_size)?;
+
+		if remap_to.is_some_and(|remap_to| !palette.contains_key(&remap_to)) {
+			return Err(InvalidField::RemapColor.into());
+		}
+
+		if patch.is_empty() {
+			return Ok(());
+		}
+
+		let remapped = connection.transaction(|connection| {
+			let remapped = color::patch_palette(&patch, &palette, remap_to, self.id, connection)?;
+			self.touch(connection)?;
+			Ok::<_, UpdateError>(remapped)
+		})?;
+
+		self.info.palette = palette.clone();
+		self.data_changed();
+
+		// Sector colors are derived from placements, so reload any which had
+		// placements remapped.
+		let remapped = remapped
+			.into_iter()
+			.filter_map(|position| {
+				self.layout
+					.to_local(position as usize)
+					.map(|local| (position, local))
+			})
+			.collect::<Vec<_>>();
+		let sectors = remapped
+			.iter()
+			.map(|(_, (sector_index, _))| *sector_index)
+			.collect::<HashSet<_>>();
+		for sector_index in sectors {
+			self.sectors.evict_sector(sector_index);
+		}
+
+		let changes = remapped
+			.into_iter()
+			.filter_map(|(position, (sector_index, sector_offset))| {
+				self.sectors
+					.read_sector(sector_index, connection)
+					.map(|sector| {
+						packet::server::Change {
+							position,
+							values: vec![sector.colors[sector_offset]],
+						}
+					})
+			})
+			.collect::<Vec<_>>();
+
+		let packet = packet::server::Packet::BoardUpdate {
+			info: Some(packet::server::BoardInfo {
+				name: None,
+				shape: None,
+				palette: Some(palette),
+				max_pixels_available: None,
+				visibility: None,
+				placement_cap: None,
+				cooldown: None,
+				frozen: None,
+			}),
+			data: (!changes.is_empty()).then_some(packet::server::BoardData {
+				colors: Some(changes),
+				timestamps: None,
+				initial: None,
+				mask: None,
+			}),
+		};
+
+		self.connections.send(packet);
+
 		Ok(())
 	}
 
+	/// Move sector data and placements to their equivalent positions in
+	/// `shape`, split into sectors by `layout`. The new shape must enclose the
+	/// current one.
+	fn reshape_data(
+		&self,
+		shape: &VecShape,
+		layout: SectorLayout,
+		connection: &mut Connection,
+	) -> Result<(), UpdateError> {
+		let old_shape = &self.info.shape;
+		let size = shape.total_size();
+		let mut mask = vec![MaskValue::NoPlace as u8; size];
+		let mut initial = vec![0; size];
+
+		for (buffer, new_data) in [
+			(SectorBuffer::Mask, &mut mask),
+			(SectorBuffer::Initial, &mut initial),
+		] {
+			let mut sector_data = self.sectors.access(buffer, connection);
+			let mut old_data = vec![0; sector_data.len()];
+			sector_data.read_exact(&mut old_data)?;
+
+			for (position, value) in old_data.into_iter().enumerate() {
+				if let Some(new_position) = VecShape::transform(old_shape, shape, position) {
+					new_data[new_position] = value;
+				}
+			}
+		}
+
+		diesel::delete(schema::board_sector::table)
+			.filter(schema::board_sector::board.eq(self.id))
+			.execute(connection)?;
+
+		let sector_size = layout.size;
+		let sectors = mask
+			.chunks(sector_size)
+			.zip(initial.chunks(sector_size))
+			.enumerate()
+			.map(|(index, (mask, initial))| {
+				model::BoardSector {
+					board: self.id,
+					index: index as i32,
+					mask: mask.to_vec(),
+					initial: initial.to_vec(),
+				}
+			})
+			.collect::<Vec<_>>();
+
+		diesel::insert_into(schema::board_sector::table)
+			.values(&sectors)
+			.execute(connection)?;
+
+		let (from, to): (Vec<i64>, Vec<i64>) = schema::placement::table
+			.select(schema::placement::position)
+			.filter(schema::placement::board.eq(self.id))
+			.distinct()
+			.load::<i64>(connection)?
+			.into_iter()
+			.filter_map(|position| {
+				VecShape::transform(old_shape, shape, position as usize)
+					.map(|new_position| (position, new_position as i64))
+			})
+			.filter(|(from, to)| from != to)
+			.unzip();
+
+		// NOTE: this is done as a single statement so that placements moved
+		// onto a position aren't then moved again by that position's mapping.
+		diesel::sql_query(
+			"UPDATE placement SET position = remap.to_position
+			FROM UNNEST($2, $3) AS remap(from_position, to_position)
+			WHERE placement.board = $1
+			AND placement.position = remap.from_position",
+		)
+		.bind::<diesel::sql_types::Int4, _>(self.id)
+		.bind::<diesel::sql_types::Array<diesel::sql_types::Int8>, _>(from)
+		.bind::<diesel::sql_types::Array<diesel::sql_types::Int8>, _>(to)
+		.execute(connection)?;
+
+		Ok(())
+	}
+
+	/// The index and size in bytes of each sector currently in memory.
+	pub fn loaded_sectors(&self) -> Vec<(usize, usize)> {
+		self.sectors.loaded_sectors()
+	}
+
+	/// Render the current colors as an indexed PNG image.
+	/// None if the board shape isn't two-dimensional.
+	pub fn colors_png(
+		&self,
+		connection: &mut Connection,
+	) -> Result<Option<Vec<u8>>, png::EncodingError> {
+		self.render_png(1, connection)
+	}
+
+	/// A downscaled PNG of the current colors, no larger than
+	/// `THUMBNAIL_SIZE` on either side.
+	/// The image is cached and only rendered again once placements have made
+	/// it stale and it is older than the configured maximum age.
+	pub fn thumbnail_png(
+		&self,
+		connection: &mut Connection,
+	) -> Result<Option<Vec<u8>>, png::EncodingError> {
+		let mut thumbnail = self.thumbnail.lock().unwrap();
+		let max_age = Duration::from_secs(CONFIG.thumbnail_max_age);
+
+		let current = thumbnail
+			.as_ref()
+			.is_some_and(|thumbnail| !thumbnail.stale || thumbnail.rendered_at.elapsed() < max_age);
+
+		if !current {
+			let scale = self
+				.info
+				.shape
+				.extents()
+				.and_then(|extents| extents.into_iter().max())
+				.map(|longest| longest.div_ceil(THUMBNAIL_SIZE))
+				.unwrap_or(1)
+				.max(1);
+
+			*thumbnail = Some(Thumbnail {
+				png: self.render_png(scale, connection)?,
+				rendered_at: Instant::now(),
+				stale: false,
+			});
+		}
+
+		Ok(thumbnail.as_ref().unwrap().png.clone())
+	}
+
+	/// A weak entity tag and modification time which change whenever any of
+	/// the board's data does.
+	pub fn data_validators(&self) -> (String, SystemTime) {
+		let etag = format!(
+			"W/\"{}-{}\"",
+			self.data_version.loaded_at,
+			self.data_version.changes.load(Ordering::Relaxed),
+		);
+		let modified_at = self.data_version.modified_at.load(Ordering::Relaxed);
+
+		(etag, UNIX_EPOCH + Duration::from_secs(modified_at))
+	}
+
+	/// Record that the board data changed, invalidating cached copies of it.
+	fn data_changed(&self) {
+		self.data_version.bump();
+
+		if let Some(ref mut thumbnail) = *self.thumbnail.lock().unwrap() {
+			thumbnail.stale = true;
+		}
+	}
+
+	/// Render the colors as an indexed PNG, taking every `scale`th pixel in
+	/// each direction.
+	fn render_png(
+		&self,
+		scale: usize,
+		connection: &mut Connection,
+	) -> Result<Option<Vec<u8>>, png::EncodingError> {
+		let shape = &self.info.shape;
+		let (height, width) = match shape.extents().as_deref() {
+			Some([height, width]) => (*height, *width),
+			_ => return Ok(None),
+		};
+		let scaled_height = height.div_ceil(scale);
+		let scaled_width = width.div_ceil(scale);
+
+		let mut colors = self.sectors.access(SectorBuffer::Colors, connection);
+		let mut row = vec![0; width];
+
+		let png = encode_png(scaled_width, scaled_height, &self.info.palette, |scaled_y, scaled_row| {
+			let y = scaled_y * scale;
+			let mut x = 0;
+			while x < width {
+				let start = shape.position(&[y, x]).unwrap();
+
+				// positions are contiguous until the row crosses a sector
+				let mut end = x + 1;
+				while end < width && shape.position(&[y, end]) == Some(start + end - x) {
+					end += 1;
+				}
+
+				colors.seek(SeekFrom::Start(u64::try_from(start).unwrap()))?;
+				colors.read_exact(&mut row[x..end])?;
+
+				x = end;
+			}
+
+			for (pixel, color) in scaled_row.iter_mut().zip(row.iter().step_by(scale)) {
+				*pixel = *color;
+			}
+
+			Ok(())
+		})?;
+
+		Ok(Some(png))
+	}
+
+	/// Stop serving the board, keeping its data so that it can be restored.
+	pub fn archive(
+		mut self,
+		connection: &mut Connection,
+	) -> QueryResult<()> {
+		self.connections.close();
+
+		diesel::update(schema::board::table)
+			.filter(schema::board::id.eq(self.id))
+			.set(schema::board::archived.eq(true))
+			.execute(connection)
+			.map(|_| ())
+	}
+
+	/// Write the board's info, sector data and placements to a compressed
+	/// archive in the backup directory.
+	/// None if backups aren't enabled.
+	pub fn backup(
+		&self,
+		connection: &mut Connection,
+	) -> Result<Option<PathBuf>, BackupError> {
+		let directory = match CONFIG.backup_dir {
+			Some(ref directory) => directory,
+			None => return Ok(None),
+		};
+
+		let time = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap()
+			.as_secs();
+
+		fs::create_dir_all(directory)?;
+		let name = format!("board-{}-{}.jsonl.gz", self.id, time);
+		let path = directory.join(&name);
+		// NOTE: the archive only gets its final name once it's complete so
+		// that a failed backup can't be mistaken for a whole one.
+		let partial_path = directory.join(format!("{}.partial", name));
+
+		let written = fs::File::create(&partial_path)
+			.map_err(BackupError::from)
+			.and_then(|file| self.write_backup(file, connection));
+
+		if let Err(error) = written {
+			if let Err(error) = fs::remove_file(&partial_path) {
+				tracing::warn!(board = self.id, %error, "failed to remove partial backup");
+			}
+			return Err(error);
+		}
+
+		fs::rename(&partial_path, &path)?;
+
+		Ok(Some(path))
+	}
+
+	fn write_backup(
+		&self,
+		file: fs::File,
+		connection: &mut Connection,
+	) -> Result<(), BackupError> {
+		const PLACEMENT_BATCH_SIZE: usize = 10000;
+
+		#[derive(Serialize)]
+		struct BackupHeader<'l> {
+			id: i32,
+			info: &'l BoardInfo,
+			sector_size: usize,
+			mask: Vec<u8>,
+			initial: Vec<u8>,
+		}
+
+		#[derive(Serialize)]
+		struct BackupPlacement<'l> {
+			position: i64,
+			color: i16,
+			timestamp: i32,
+			user_id: &'l Option<String>,
+		}
+
+		let mask_data = {
+			let mut mask = self.sectors.access(SectorBuffer::Mask, connection);
+			let mut mask_data = vec![0; mask.len()];
+			mask.read_exact(&mut mask_data)?;
+			mask_data
+		};
+
+		let initial_data = {
+			let mut initial = self.sectors.access(SectorBuffer::Initial, connection);
+			let mut initial_data = vec![0; initial.len()];
+			initial.read_exact(&mut initial_data)?;
+			initial_data
+		};
+
+		let mut archive = GzEncoder::new(file, Compression::default());
+
+		let header = BackupHeader {
+			id: self.id,
+			info: &self.info,
+			sector_size: self.layout.size,
+			mask: mask_data,
+			initial: initial_data,
+		};
+		serde_json::to_writer(&mut archive, &header).map_err(io::Error::from)?;
+		archive.write_all(b"\n")?;
+
+		// Placements follow the header, one per line, oldest first.
+		let filter = PlacementFilter::default();
+		let (mut timestamp, mut id) = (0, 0);
+		loop {
+			let placements = self.list_placements(
+				timestamp,
+				id,
+				PLACEMENT_BATCH_SIZE,
+				false,
+				&filter,
+				connection,
+			)?;
+
+			for placement in &placements {
+				let placement = BackupPlacement {
+					position: placement.position,
+					color: placement.color,
+					timestamp: placement.timestamp,
+					user_id: &placement.user_id,
+				};
+				serde_json::to_writer(&mut archive, &placement).map_err(io::Error::from)?;
+				archive.write_all(b"\n")?;
+			}
+
+			match placements.last() {
+				Some(last) if placements.len() == PLACEMENT_BATCH_SIZE => {
+					timestamp = last.timestamp as u32;
+					id = last.id as usize + 1;
+				},
+				_ => break,
+			}
+		}
+
+		archive.finish()?.sync_all()?;
+
+		Ok(())
+	}
+
+	/// Permanently remove the board and all of its data.
 	pub fn delete(
 		mut self,
 		connection: &mut Connection,
@@ -643,7 +2074,7 @@ impl Board {
 			.filter(
 				schema::placement::board
 					.eq(self.id)
-					.and(schema::placement::user_id.eq(user.id.clone())),
+					.and(schema::placement::user_id.is_not_distinct_from(user.id.clone())),
 			)
 			.order((
 				schema::placement::timestamp.desc(),
@@ -656,55 +2087,176 @@ impl Board {
 			.unwrap_or(0))
 	}
 
-	pub fn try_place(
+	/// Run every check a placement must pass without placing anything or
+	/// creating missing sectors.
+	/// Returns the number of pixels the placement would change.
+	pub fn dry_place(
 		&self,
 		user: &User,
 		position: u64,
 		color: u8,
 		connection: &mut Connection,
-	) -> Result<model::Placement, PlaceError> {
-		// TODO: I hate most things about how this is written. Redo it and/or move
-		// stuff.
+	) -> Result<usize, PlaceError> {
+		if self.info.frozen {
+			return Err(PlaceError::Frozen);
+		}
 
 		let (sector_index, sector_offset) = self
-			.info
-			.shape
+			.layout
 			.to_local(position as usize)
 			.ok_or(PlaceError::OutOfBounds)?;
 
 		if !self.info.palette.contains_key(&(color as u32)) {
 			return Err(PlaceError::InvalidColor);
 		}
-		
-		let mut sector = self
-			.sectors
-			.write_sector(sector_index, connection)
-			.expect("Failed to load sector");
-
-		match FromPrimitive::from_u8(sector.mask[sector_offset]) {
-			Some(MaskValue::Place) => Ok(()),
-			Some(MaskValue::NoPlace) => Err(PlaceError::Unplacable),
-			// NOTE: there exists an old implementation in the version
-			// control history. It's messy and would need to load adjacent
-			// sectors now so I'm dropping it for now.
-			Some(MaskValue::Adjacent) => unimplemented!(),
-			None => Err(PlaceError::UnknownMaskValue),
-		}?;
 
-		if sector.colors[sector_offset] == color {
-			return Err(PlaceError::NoOp);
-		}
+		let sector = match self.sectors.read_existing_sector(sector_index, connection)? {
+			Some(sector) => sector,
+			// New sectors would be created with a mask which can't be placed on.
+			None => return Err(PlaceError::Unplacable),
+		};
 
-		let timestamp = self.current_timestamp();
-		let cooldown_info = self
-			.user_cooldown_info(user, connection)
-			.unwrap();
+		self.check_place(&sector, sector_offset, user, color, connection)?;
 
-		if cooldown_info.pixels_available == 0 {
-			return Err(PlaceError::Cooldown);
-		}
+		// A placement only ever changes its own pixel, and placing the color
+		// already there is rejected as a no-op.
+		Ok(1)
+	}
 
-		let new_placement = diesel::insert_into(schema::placement::table)
+	/// Check whether the user could place any color at a position.
+	pub fn placeability(
+		&self,
+		user: &User,
+		position: u64,
+		connection: &mut Connection,
+	) -> Result<Placeability, PlaceError> {
+		let (sector_index, sector_offset) = self
+			.layout
+			.to_local(position as usize)
+			.ok_or(PlaceError::OutOfBounds)?;
+
+		let mask = match self.sectors.read_existing_sector(sector_index, connection)? {
+			Some(sector) => {
+				FromPrimitive::from_u8(sector.mask[sector_offset])
+					.ok_or(PlaceError::UnknownMaskValue)?
+			},
+			// New sectors would be created with a mask which can't be placed on.
+			None => MaskValue::NoPlace,
+		};
+
+		let check = if self.info.frozen {
+			Err(PlaceError::Frozen)
+		} else if mask != MaskValue::Place {
+			// NOTE: adjacent placement isn't supported (see check_place).
+			Err(PlaceError::Unplacable)
+		} else {
+			let cost = self
+				.info
+				.palette
+				.values()
+				.map(Color::cost)
+				.min()
+				.unwrap_or(1);
+
+			self.check_user(user, cost, connection)
+		};
+
+		if let Err(PlaceError::DatabaseError(error)) = check {
+			return Err(PlaceError::DatabaseError(error));
+		}
+
+		Ok(Placeability {
+			mask,
+			placeable: check.is_ok(),
+			reason: check.err().map(|err| err.code()),
+		})
+	}
+
+	fn check_place(
+		&self,
+		sector: &BoardSector,
+		sector_offset: usize,
+		user: &User,
+		color: u8,
+		connection: &mut Connection,
+	) -> Result<(), PlaceError> {
+		match FromPrimitive::from_u8(sector.mask[sector_offset]) {
+			Some(MaskValue::Place) => Ok(()),
+			Some(MaskValue::NoPlace) => Err(PlaceError::Unplacable),
+			// NOTE: there exists an old implementation in the version
+			// control history. It's messy and would need to load adjacent
+			// sectors now so I'm dropping it for now.
+			// Until then, treat these as unplaceable rather than panicking.
+			Some(MaskValue::Adjacent) => Err(PlaceError::Unplacable),
+			None => Err(PlaceError::UnknownMaskValue),
+		}?;
+
+		if sector.colors[sector_offset] == color {
+			return Err(PlaceError::NoOp);
+		}
+
+		self.check_user(user, self.placement_cost(color), connection)
+	}
+
+	/// Check the user has the pixels available and hasn't reached the cap.
+	fn check_user(
+		&self,
+		user: &User,
+		cost: usize,
+		connection: &mut Connection,
+	) -> Result<(), PlaceError> {
+		let cooldown_info = self.user_cooldown_info(user, connection)?;
+
+		if cooldown_info.pixels_available < cost {
+			return Err(PlaceError::Cooldown);
+		}
+
+		if let Some(placement_cap) = self.info.placement_cap {
+			let placed = self.user_placement_count(user, connection)?;
+			if placed >= placement_cap as usize {
+				return Err(PlaceError::CapReached);
+			}
+		}
+
+		Ok(())
+	}
+
+	#[tracing::instrument(skip(self, user, connection), fields(board = self.id, user = ?user.id))]
+	pub fn try_place(
+		&self,
+		user: &User,
+		position: u64,
+		color: u8,
+		connection: &mut Connection,
+	) -> Result<model::Placement, PlaceError> {
+		// TODO: I hate most things about how this is written. Redo it and/or move
+		// stuff.
+
+		if self.info.frozen {
+			return Err(PlaceError::Frozen);
+		}
+
+		let (sector_index, sector_offset) = self
+			.layout
+			.to_local(position as usize)
+			.ok_or(PlaceError::OutOfBounds)?;
+
+		if !self.info.palette.contains_key(&(color as u32)) {
+			return Err(PlaceError::InvalidColor);
+		}
+		
+		// NOTE: the sector stays locked until the placement is stored so that
+		// concurrent placements can't both pass the cooldown check.
+		let mut sector = self
+			.sectors
+			.write_sector(sector_index, connection)?
+			.ok_or(PlaceError::OutOfBounds)?;
+
+		self.check_place(&sector, sector_offset, user, color, connection)?;
+
+		let timestamp = self.current_timestamp();
+
+		let new_placement = diesel::insert_into(schema::placement::table)
 			.values(model::NewPlacement {
 				board: self.id,
 				position: position as i64,
@@ -712,15 +2264,26 @@ impl Board {
 				timestamp: timestamp as i32,
 				user_id: user.id.clone(),
 			})
-			.get_result::<model::Placement>(connection)
-			.expect("failed to insert placement");
+			.get_result::<model::Placement>(connection)?;
+
+		self.info.last_placement_at.store(
+			self.info.created_at + u64::from(timestamp),
+			Ordering::Relaxed,
+		);
 
 		sector.colors[sector_offset] = color;
+		self.data_changed();
 		let timestamp_slice =
 			&mut sector.timestamps[(sector_offset * 4)..((sector_offset + 1) * 4)];
 		timestamp_slice
 			.as_mut()
 			.put_u32_le(timestamp);
+		let density_slice =
+			&mut sector.density[(sector_offset * 4)..((sector_offset + 1) * 4)];
+		let density = u32::from_le_bytes((&*density_slice).try_into().unwrap());
+		density_slice
+			.as_mut()
+			.put_u32_le(density + 1);
 
 		let packet = packet::server::Packet::BoardUpdate {
 			info: None,
@@ -741,9 +2304,7 @@ impl Board {
 		self.connections.send(packet);
 
 		if let Some(user_id) = user.id.clone() {
-			let cooldown_info = self
-				.user_cooldown_info(user, connection)
-				.unwrap();
+			let cooldown_info = self.user_cooldown_info(user, connection)?;
 
 			self.connections
 				.set_user_cooldown(user_id, cooldown_info);
@@ -752,42 +2313,129 @@ impl Board {
 		Ok(new_placement)
 	}
 
+	/// Delete every placement at a position and return it to its initial
+	/// color. Returns the number of placements removed, or `None` if the
+	/// position is outside the board.
+	/// Users who made the placements are sent their new cooldown since the
+	/// removed placements no longer count against them.
+	pub fn clear_pixel(
+		&self,
+		position: u64,
+		connection: &mut Connection,
+	) -> QueryResult<Option<usize>> {
+		let (sector_index, sector_offset) = match self
+			.layout
+			.to_local(position as usize)
+		{
+			Some(local) => local,
+			None => return Ok(None),
+		};
+
+		let mut sector = match self.sectors.write_sector(sector_index, connection)? {
+			Some(sector) => sector,
+			None => return Ok(None),
+		};
+
+		let placers = diesel::delete(schema::placement::table)
+			.filter(
+				schema::placement::board
+					.eq(self.id)
+					.and(schema::placement::position.eq(position as i64)),
+			)
+			.returning(schema::placement::user_id)
+			.get_results::<Option<String>>(connection)?;
+		let removed = placers.len();
+
+		let color = sector.initial[sector_offset];
+		sector.colors[sector_offset] = color;
+		self.data_changed();
+		self.cleared_at.fetch_max(self.current_timestamp(), Ordering::Relaxed);
+		sector.timestamps[(sector_offset * 4)..((sector_offset + 1) * 4)].fill(0);
+		sector.density[(sector_offset * 4)..((sector_offset + 1) * 4)].fill(0);
+
+		let packet = packet::server::Packet::BoardUpdate {
+			info: None,
+			data: Some(packet::server::BoardData {
+				colors: Some(vec![packet::server::Change {
+					position,
+					values: vec![color],
+				}]),
+				timestamps: Some(vec![packet::server::Change {
+					position,
+					values: vec![0],
+				}]),
+				initial: None,
+				mask: None,
+			}),
+		};
+
+		self.connections.send(packet);
+		drop(sector);
+
+		let placers = placers
+			.into_iter()
+			.flatten()
+			.collect::<HashSet<_>>();
+		for user_id in placers {
+			let user = User::from_id(user_id.clone());
+			let cooldown_info = self.user_cooldown_info(&user, connection)?;
+			self.connections
+				.set_user_cooldown(user_id, cooldown_info);
+		}
+
+		Ok(Some(removed))
+	}
+
 	pub fn list_placements(
 		&self,
 		timestamp: u32,
 		id: usize,
 		limit: usize,
 		reverse: bool,
+		filter: &PlacementFilter,
 		connection: &mut Connection,
 	) -> QueryResult<Vec<model::Placement>> {
+		let mut query = schema::placement::table
+			.filter(schema::placement::board.eq(self.id))
+			.into_boxed();
+
+		if let Some(ref colors) = filter.colors {
+			let colors = colors
+				.iter()
+				.map(|&color| i16::from(color))
+				.collect::<Vec<_>>();
+			query = query.filter(schema::placement::color.eq_any(colors));
+		}
+
+		if let Some(ref user) = filter.user {
+			query = query.filter(schema::placement::user_id.eq(user));
+		}
+
 		// TODO: Reduce duplication.
-		// This stems from le and ge having different types, polluting the entire
+		// This stems from lt and ge having different types, polluting the entire
 		// expression. I suppose the original also had duplication in the sql query,
 		// but I guess I was more okay with that?
 		if reverse {
-			schema::placement::table
+			// NOTE: results are newest first so that the limit selects the
+			// placements immediately before the token rather than the oldest.
+			query
 				.filter(
-					schema::placement::board
-						.eq(self.id)
-						.and(
-							(schema::placement::timestamp, schema::placement::id)
-								.into_sql::<Record<_>>()
-								.le((timestamp as i32, id as i64)),
-						),
+					(schema::placement::timestamp, schema::placement::id)
+						.into_sql::<Record<_>>()
+						.lt((timestamp as i32, id as i64)),
 				)
-				.order((schema::placement::timestamp, schema::placement::id))
+				.order((
+					schema::placement::timestamp.desc(),
+					schema::placement::id.desc(),
+				))
 				.limit(limit as i64)
 				.load::<model::Placement>(connection)
 		} else {
-			schema::placement::table
+			query
 				.filter(
-					schema::placement::board
-						.eq(self.id)
-						.and(
-							(schema::placement::timestamp, schema::placement::id)
-								.into_sql::<Record<_>>()
-								.ge((timestamp as i32, id as i64)),
-						),
+					(schema::placement::timestamp, schema::placement::id)
+						.into_sql::<Record<_>>()
+						.ge((timestamp as i32, id as i64)),
 				)
 				.order((schema::placement::timestamp, schema::placement::id))
 				.limit(limit as i64)
@@ -815,6 +2463,38 @@ impl Board {
 			.pop())
 	}
 
+	/// Look up the current placement at each position, in the same order.
+	pub fn lookup_many(
+		&self,
+		positions: &[u64],
+		connection: &mut Connection,
+	) -> QueryResult<Vec<Option<model::Placement>>> {
+		let mut unique_positions = positions
+			.iter()
+			.map(|position| *position as i64)
+			.collect::<Vec<_>>();
+		unique_positions.sort_unstable();
+		unique_positions.dedup();
+
+		let placements = diesel::sql_query(
+			"SELECT DISTINCT ON (position) * FROM placement
+			WHERE board = $1
+			AND position = ANY($2)
+			ORDER BY position, timestamp DESC, id DESC",
+		)
+		.bind::<diesel::sql_types::Int4, _>(self.id)
+		.bind::<diesel::sql_types::Array<diesel::sql_types::Int8>, _>(unique_positions)
+		.load::<model::Placement>(connection)?
+		.into_iter()
+		.map(|placement| (placement.position, placement))
+		.collect::<HashMap<_, _>>();
+
+		Ok(positions
+			.iter()
+			.map(|position| placements.get(&(*position as i64)).cloned())
+			.collect())
+	}
+
 	pub fn load(
 		board: model::Board,
 		connection: &mut Connection,
@@ -827,28 +2507,56 @@ impl Board {
 			.map(|color| (color.index as u32, Color::from(color)))
 			.collect::<Palette>();
 
+		let last_placement_at = schema::placement::table
+			.select(diesel::dsl::max(schema::placement::timestamp))
+			.filter(schema::placement::board.eq(id))
+			.first::<Option<i32>>(connection)?
+			.map(|timestamp| board.created_at as u64 + timestamp as u64)
+			.unwrap_or(0);
+
 		let info = BoardInfo {
 			name: board.name.clone(),
 			created_at: board.created_at as u64,
 			shape: serde_json::from_value(board.shape).unwrap(),
 			palette,
 			max_pixels_available: board.max_stacked as u32,
+			visibility: Visibility::from_str(&board.visibility),
+			placement_cap: board.placement_cap.map(|cap| cap as u32),
+			cooldown: board.cooldown.map(|cooldown| cooldown as u32),
+			frozen: board.frozen,
+			updated_at: board.updated_at as u64,
+			last_placement_at: AtomicU64::new(last_placement_at),
 		};
 
-		let sectors = SectorCache::new(
-			board.id,
-			info.shape.sector_count(),
-			info.shape.sector_size(),
-		);
+		let layout = match board.sector_size {
+			Some(size) => SectorLayout {
+				size: size as usize,
+				count: info.shape.total_size() / size as usize,
+			},
+			None => SectorLayout::of(&info.shape),
+		};
+
+		let sectors = SectorCache::new(board.id, layout.count, layout.size);
 
 		let connections = Connections::default();
 
-		Ok(Board {
+		let board = Board {
 			id,
 			info,
+			layout,
 			sectors,
 			connections,
-		})
+			last_user_count: Mutex::new(None),
+			thumbnail: Mutex::new(None),
+			data_version: DataVersion::new(),
+			cleared_at: AtomicU32::new(0),
+		};
+
+		// NOTE: removals made before loading aren't known so they're assumed
+		// to have happened just now.
+		board.cleared_at.store(board.current_timestamp(), Ordering::Relaxed);
+
+		Ok(board)
 	}
 
 	fn current_timestamp(&self) -> u32 {
@@ -874,8 +2582,9 @@ impl Board {
 		schema::placement::table
 			.select(diesel::dsl::count_star())
 			.filter(
-				schema::placement::position
-					.eq(position as i64)
+				schema::placement::board
+					.eq(self.id)
+					.and(schema::placement::position.eq(position as i64))
 					.and(schema::placement::timestamp.lt(timestamp as i32)),
 			)
 			.first(connection)
@@ -892,7 +2601,7 @@ impl Board {
 	) -> QueryResult<Vec<SystemTime>> {
 		// this is pretty ugly
 		// TODO: generalize for more cooldown variables
-		let (activity, density) = if let Some(placement) = placement {
+		let (_activity, density) = if let Some(placement) = placement {
 			(
 				self.user_count_for_time(placement.timestamp as u32, connection)?.active,
 				self.pixel_density_at_time(
@@ -907,23 +2616,78 @@ impl Board {
 
 		let board_time = self.info.created_at;
 
-		// TODO: proper cooldown
-		Ok(std::iter::repeat(30)
-			.enumerate()
-			.map(|(i, c)| u32::try_from((i + 1) * c).unwrap())
-			.zip(std::iter::repeat(
-				placement
-					.map(|p| p.timestamp as u32)
-					.unwrap_or(0),
-			))
-			.map(|(a, b)| a + b)
-			.take(usize::try_from(self.info.max_pixels_available).unwrap())
+		let last_placement = placement
+			.map(|p| p.timestamp as u32)
+			.unwrap_or(0);
+
+		let offsets = cooldown_offsets(
+			&config::tunables(),
+			self.info.cooldown,
+			density,
+			usize::try_from(self.info.max_pixels_available).unwrap(),
+		);
+
+		Ok(offsets
+			.into_iter()
+			.map(|offset| last_placement.saturating_add(offset))
 			.map(|offset| board_time + offset as u64)
 			.map(Duration::from_secs)
 			.map(|offset| UNIX_EPOCH + offset)
 			.collect())
 	}
 
+	/// The palette entry at an index.
+	pub fn color(
+		&self,
+		index: u32,
+	) -> Option<&Color> {
+		self.info.palette.get(&index)
+	}
+
+	/// The number of pixels currently of a color.
+	pub fn color_usage(
+		&self,
+		color: u8,
+		connection: &mut Connection,
+	) -> io::Result<usize> {
+		Ok(self.color_counts(connection)?[color as usize])
+	}
+
+	/// The number of pixels currently of each color, omitting unused colors.
+	pub fn color_histogram(
+		&self,
+		connection: &mut Connection,
+	) -> io::Result<BTreeMap<u32, usize>> {
+		Ok(self.color_counts(connection)?
+			.into_iter()
+			.enumerate()
+			.filter(|(_, count)| *count > 0)
+			.map(|(color, count)| (color as u32, count))
+			.collect())
+	}
+
+	/// The number of pixels currently of each color index.
+	fn color_counts(
+		&self,
+		connection: &mut Connection,
+	) -> io::Result<[usize; 256]> {
+		let mut colors = self.sectors.access(SectorBuffer::Colors, connection);
+		let len = colors.len();
+		count_colors(&mut colors, len, self.layout.size)
+	}
+
+	/// The number of available pixels placing `color` consumes.
+	fn placement_cost(
+		&self,
+		color: u8,
+	) -> usize {
+		self.info
+			.palette
+			.get(&u32::from(color))
+			.map(Color::cost)
+			.unwrap_or(1)
+	}
+
 	fn recent_user_placements(
 		&self,
 		user: &User,
@@ -934,7 +2698,7 @@ impl Board {
 			.filter(
 				schema::placement::board
 					.eq(self.id)
-					.and(schema::placement::user_id.eq(user.id.as_ref())),
+					.and(schema::placement::user_id.is_not_distinct_from(user.id.clone())),
 			)
 			.order((
 				schema::placement::timestamp.desc(),
@@ -1007,22 +2771,89 @@ impl Board {
 
 				pixels = pixels
 					.max(info.pixels_available)
-					.saturating_sub(1);
+					.saturating_sub(self.placement_cost(pair[1].color as u8));
 			}
 
 			info.pixels_available = info.pixels_available.max(pixels);
 		}
 
+		info.recent_placements = self.recent_placement_count(user, connection)?;
+
 		Ok(info)
 	}
 
+	/// The users with the most placements on the board and their placement
+	/// counts, most first. Anonymous placements aren't included.
+	pub fn leaderboard(
+		&self,
+		limit: usize,
+		connection: &mut Connection,
+	) -> QueryResult<Vec<(String, usize)>> {
+		let placements = diesel::dsl::count_star();
+
+		Ok(schema::placement::table
+			.filter(
+				schema::placement::board
+					.eq(self.id)
+					.and(schema::placement::user_id.is_not_null()),
+			)
+			.group_by(schema::placement::user_id)
+			.select((schema::placement::user_id, placements))
+			.order((placements.desc(), schema::placement::user_id.asc()))
+			.limit(i64::try_from(limit).unwrap())
+			.load::<(Option<String>, i64)>(connection)?
+			.into_iter()
+			.filter_map(|(user_id, count)| {
+				user_id.map(|user_id| (user_id, usize::try_from(count).unwrap()))
+			})
+			.collect())
+	}
+
+	/// The number of placements a user has made on the board.
+	fn user_placement_count(
+		&self,
+		user: &User,
+		connection: &mut Connection,
+	) -> QueryResult<usize> {
+		schema::placement::table
+			.select(diesel::dsl::count_star())
+			.filter(
+				schema::placement::board
+					.eq(self.id)
+					.and(schema::placement::user_id.is_not_distinct_from(user.id.clone())),
+			)
+			.first(connection)
+			.map(|count: i64| usize::try_from(count).unwrap())
+	}
+
+	/// The number of placements a user has made within the idle timeout.
+	fn recent_placement_count(
+		&self,
+		user: &User,
+		connection: &mut Connection,
+	) -> QueryResult<usize> {
+		let since = self
+			.current_timestamp()
+			.saturating_sub(config::tunables().idle_timeout);
+
+		schema::placement::table
+			.select(diesel::dsl::count_star())
+			.filter(
+				schema::placement::board
+					.eq(self.id)
+					.and(schema::placement::user_id.is_not_distinct_from(user.id.clone()))
+					.and(schema::placement::timestamp.ge(since as i32)),
+			)
+			.first(connection)
+			.map(|count: i64| usize::try_from(count).unwrap())
+	}
+
 	fn user_count_for_time(
 		&self,
 		timestamp: u32,
 		connection: &mut Connection,
 	) -> QueryResult<UserCount> {
-		// TODO: make configurable
-		let idle_timeout = 5 * 60;
+		let idle_timeout = config::tunables().idle_timeout;
 		let max_time = i32::try_from(timestamp).unwrap();
 		let min_time = i32::try_from(timestamp.saturating_sub(idle_timeout)).unwrap();
 
@@ -1068,13 +2899,169 @@ impl Board {
 		self.user_count_for_time(self.current_timestamp(), connection)
 	}
 
+	/// Send the user count to sockets if it changed since it was last sent.
+	pub fn update_user_count(
+		&self,
+		connection: &mut Connection,
+	) -> QueryResult<()> {
+		let user_count = self.user_count(connection)?;
+		let mut last_user_count = self.last_user_count.lock().unwrap();
+
+		if *last_user_count != Some(user_count.active) {
+			*last_user_count = Some(user_count.active);
+
+			self.connections.send(packet::server::Packet::UserCount {
+				active: user_count.active,
+				idle_timeout: user_count.idle_timeout,
+			});
+		}
+
+		Ok(())
+	}
+
+	/// Whether the board has as many sockets as it allows.
+	pub fn sockets_full(&self) -> bool {
+		CONFIG
+			.max_sockets_per_board
+			.is_some_and(|max| self.connections.len() >= max)
+	}
+
+	/// Add a socket to the board, returning false if the board is full.
+	/// If `since` is set, changes made after that time are sent first.
 	pub fn insert_socket(
 		&mut self,
 		socket: Arc<AuthedSocket>,
+		since: Option<u32>,
+		connection: &mut Connection,
+	) -> QueryResult<bool> {
+		if self.sockets_full() {
+			return Ok(false);
+		}
+
+		let cooldown_info = self.socket_cooldown_info(&socket, connection)?;
+
+		if let Some(since) = since {
+			let packet = self.replay(since, connection)?;
+			let packet = match socket.region {
+				Some(ref region) => packet.within(region),
+				None => Some(packet),
+			};
+			if let Some(packet) = packet {
+				socket.send(&packet);
+			}
+		}
+
+		self.connections
+			.insert(Arc::clone(&socket), cooldown_info);
+		socket.send(&packet::server::Packet::Ready);
+
+		Ok(true)
+	}
+
+	/// A packet bringing a client up to date with changes made at or after
+	/// `since`. Timestamps only have a resolution of one second so this may
+	/// include changes the client has already seen; clients should expect
+	/// duplicates.
+	///
+	/// Cleared pixels aren't replayed since their placements no longer exist.
+	/// If any were cleared at or after `since`, or there are too many
+	/// changes, the client is told to refetch instead.
+	fn replay(
+		&self,
+		since: u32,
+		connection: &mut Connection,
+	) -> QueryResult<packet::server::Packet> {
+		if self.cleared_at.load(Ordering::Relaxed) >= since {
+			return Ok(packet::server::Packet::Refetch);
+		}
+
+		let limit = CONFIG.socket_replay_limit;
+
+		let placements = schema::placement::table
+			.filter(
+				schema::placement::board
+					.eq(self.id)
+					.and(schema::placement::timestamp.ge(since as i32)),
+			)
+			.order((
+				schema::placement::timestamp.asc(),
+				schema::placement::id.asc(),
+			))
+			.limit(limit as i64 + 1)
+			.load::<model::Placement>(connection)?;
+
+		if placements.len() > limit {
+			return Ok(packet::server::Packet::Refetch);
+		}
+
+		// later placements replace earlier ones at the same position
+		let latest = placements
+			.into_iter()
+			.map(|placement| (placement.position as u64, placement))
+			.collect::<BTreeMap<_, _>>();
+
+		let colors = latest
+			.values()
+			.map(|placement| packet::server::Change {
+				position: placement.position as u64,
+				values: vec![placement.color as u8],
+			})
+			.collect::<Vec<_>>();
+
+		let timestamps = latest
+			.values()
+			.map(|placement| packet::server::Change {
+				position: placement.position as u64,
+				values: vec![placement.timestamp as u32],
+			})
+			.collect::<Vec<_>>();
+
+		Ok(packet::server::Packet::BoardUpdate {
+			info: None,
+			data: Some(packet::server::BoardData {
+				colors: Some(colors),
+				timestamps: Some(timestamps),
+				initial: None,
+				mask: None,
+			}),
+		})
+	}
+
+	/// Change the user a connected socket acts as and send it their cooldown.
+	pub fn reauthenticate_socket(
+		&mut self,
+		socket: Arc<AuthedSocket>,
+		user: AuthedUser,
 		connection: &mut Connection,
 	) -> QueryResult<()> {
+		self.connections.remove(Arc::clone(&socket));
+		*socket.user.write() = user;
+
+		let cooldown_info = self.socket_cooldown_info(&socket, connection)?;
+		let user_id = Option::<&User>::from(&*socket.user.read())
+			.and_then(|user| user.id.clone());
+
+		self.connections
+			.insert(Arc::clone(&socket), cooldown_info.clone());
+
+		// Other sockets of the user already know their cooldown, but this one
+		// may not.
+		if let (Some(user_id), Some(cooldown_info)) = (user_id, cooldown_info) {
+			self.connections
+				.set_user_cooldown(user_id, cooldown_info);
+		}
+
+		Ok(())
+	}
+
+	/// The cooldown of a socket's user, if it has one.
+	fn socket_cooldown_info(
+		&self,
+		socket: &AuthedSocket,
+		connection: &mut Connection,
+	) -> QueryResult<Option<CooldownInfo>> {
 		let user = socket.user.read();
-		let cooldown_info = Option::<&User>::from(&*user)
+		Option::<&User>::from(&*user)
 			.and_then(|user| {
 				if user.id.is_some() {
 					Some(self.user_cooldown_info(user, connection))
@@ -1082,13 +3069,7 @@ impl Board {
 					None
 				}
 			})
-			.transpose()?;
-
-		self.connections
-			.insert(Arc::clone(&socket), cooldown_info);
-		socket.send(&packet::server::Packet::Ready);
-
-		Ok(())
+			.transpose()
 	}
 
 	pub fn remove_socket(
@@ -1115,3 +3096,639 @@ impl<'l> From<&'l Board> for Reference<'l, BoardInfo> {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn user_with(permissions: &[Permission]) -> AuthedUser {
+		AuthedUser::Authed {
+			user: User {
+				id: Some("user".to_owned()),
+				permissions: permissions.iter().copied().collect(),
+			},
+			valid_until: SystemTime::now() + Duration::from_secs(60),
+		}
+	}
+
+	/// A pool for the database at `DATABASE_URL`.
+	/// Tests using this also need the rest of the required configuration.
+	fn test_pool() -> crate::database::Pool {
+		let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+		crate::database::Pool::builder()
+			.max_size(4)
+			.build(diesel::r2d2::ConnectionManager::new(url))
+			.expect("Failed to connect to the test database")
+	}
+
+	/// A new board which can be placed on anywhere.
+	fn test_board(
+		max_pixels_available: u32,
+		connection: &mut Connection,
+	) -> Board {
+		let info = serde_json::from_value::<BoardInfoPost>(serde_json::json!({
+			"name": "Test",
+			"shape": [[1, 1], [16, 16]],
+			"palette": {
+				"0": { "name": "White", "value": 16777215 },
+				"1": { "name": "Black", "value": 0 },
+			},
+			"max_pixels_available": max_pixels_available,
+		}))
+		.unwrap();
+
+		let board = Board::create(info, connection).unwrap();
+		for index in 0..board.layout.count {
+			board.sectors
+				.write_sector(index, connection)
+				.unwrap()
+				.unwrap()
+				.mask
+				.fill(MaskValue::Place as u8);
+		}
+
+		board
+	}
+
+	#[test]
+	#[ignore = "needs a database"]
+	fn only_one_placement_takes_the_last_pixel() {
+		let pool = test_pool();
+		let mut connection = pool.get().unwrap();
+		let board = test_board(1, &mut connection);
+		let user = User::from_id("racer".to_owned());
+		let barrier = std::sync::Barrier::new(2);
+
+		let results = std::thread::scope(|scope| {
+			let attempts = [1, 2].map(|position| {
+				let (board, user, barrier, pool) = (&board, &user, &barrier, &pool);
+				scope.spawn(move || {
+					let mut connection = pool.get().unwrap();
+					barrier.wait();
+					board.try_place(user, position, 1, &mut connection)
+				})
+			});
+
+			attempts.map(|attempt| attempt.join().unwrap())
+		});
+
+		board.delete(&mut connection).unwrap();
+
+		let placed = results
+			.iter()
+			.filter(|result| result.is_ok())
+			.count();
+		let limited = results
+			.iter()
+			.filter(|result| matches!(result, Err(PlaceError::Cooldown)))
+			.count();
+		assert_eq!(placed, 1);
+		assert_eq!(limited, 1);
+	}
+
+	#[test]
+	#[ignore = "needs a database"]
+	fn frozen_boards_reject_placements() {
+		let pool = test_pool();
+		let mut connection = pool.get().unwrap();
+		let mut board = test_board(10, &mut connection);
+		let user = User::from_id("placer".to_owned());
+
+		let freeze = |frozen: bool| {
+			serde_json::from_value::<BoardInfoPatch>(serde_json::json!({ "frozen": frozen }))
+				.unwrap()
+		};
+
+		board.update_info(freeze(true), &mut connection).unwrap();
+		let dry_run = board.dry_place(&user, 0, 1, &mut connection);
+		let frozen = board.try_place(&user, 0, 1, &mut connection);
+
+		board.update_info(freeze(false), &mut connection).unwrap();
+		let unfrozen = board.try_place(&user, 0, 1, &mut connection);
+
+		board.delete(&mut connection).unwrap();
+
+		assert!(matches!(dry_run, Err(PlaceError::Frozen)));
+		assert!(matches!(frozen, Err(PlaceError::Frozen)));
+		assert!(unfrozen.is_ok());
+	}
+
+	#[test]
+	#[ignore = "needs a database"]
+	fn placements_stop_at_the_cap() {
+		let pool = test_pool();
+		let mut connection = pool.get().unwrap();
+		let mut board = test_board(10, &mut connection);
+		let user = User::from_id("capped".to_owned());
+
+		let cap = serde_json::from_value::<BoardInfoPatch>(serde_json::json!({ "placement_cap": 1 }))
+			.unwrap();
+		board.update_info(cap, &mut connection).unwrap();
+
+		let first = board.try_place(&user, 0, 1, &mut connection);
+		let second = board.try_place(&user, 1, 1, &mut connection);
+		let other = board.try_place(&User::from_id("uncapped".to_owned()), 1, 1, &mut connection);
+
+		board.delete(&mut connection).unwrap();
+
+		assert!(first.is_ok());
+		assert!(matches!(second, Err(PlaceError::CapReached)));
+		assert!(other.is_ok());
+	}
+
+	#[test]
+	#[ignore = "needs a database"]
+	fn removed_colors_are_remapped() {
+		let pool = test_pool();
+		let mut connection = pool.get().unwrap();
+		let mut board = test_board(10, &mut connection);
+		let user = User::from_id("painter".to_owned());
+		let remove_black = || {
+			serde_json::from_value::<PalettePatch>(serde_json::json!({ "1": null })).unwrap()
+		};
+
+		board.try_place(&user, 0, 1, &mut connection).unwrap();
+
+		let in_use = board.patch_palette(remove_black(), None, &mut connection);
+		let missing_target = board.patch_palette(remove_black(), Some(1), &mut connection);
+		let remapped = board.patch_palette(remove_black(), Some(0), &mut connection);
+		let color = board.sectors
+			.read_sector(0, &mut connection)
+			.unwrap()
+			.colors[0];
+		let palette_size = board.info.palette.len();
+
+		board.delete(&mut connection).unwrap();
+
+		assert!(matches!(in_use, Err(UpdateError::ColorsInUse(colors)) if colors == vec![1]));
+		assert!(matches!(missing_target, Err(UpdateError::Invalid(InvalidField::RemapColor))));
+		assert!(remapped.is_ok());
+		assert_eq!(color, 0);
+		assert_eq!(palette_size, 1);
+	}
+
+	#[test]
+	#[ignore = "needs a database"]
+	fn leaderboards_rank_users_by_placements() {
+		let pool = test_pool();
+		let mut connection = pool.get().unwrap();
+		let board = test_board(10, &mut connection);
+
+		let placements = [Some("few"), Some("many"), None, Some("many"), None, None]
+			.into_iter()
+			.enumerate()
+			.map(|(position, user_id)| {
+				model::NewPlacement {
+					board: board.id,
+					position: position as i64,
+					color: 1,
+					timestamp: 1,
+					user_id: user_id.map(str::to_owned),
+				}
+			})
+			.collect::<Vec<_>>();
+		diesel::insert_into(schema::placement::table)
+			.values(&placements)
+			.execute(&mut connection)
+			.unwrap();
+
+		let leaderboard = board.leaderboard(10, &mut connection);
+		let limited = board.leaderboard(1, &mut connection);
+
+		board.delete(&mut connection).unwrap();
+
+		assert_eq!(leaderboard.unwrap(), vec![
+			("many".to_owned(), 2),
+			("few".to_owned(), 1),
+		]);
+		assert_eq!(limited.unwrap(), vec![("many".to_owned(), 2)]);
+	}
+
+	#[test]
+	#[ignore = "needs a database"]
+	fn concurrent_accesses_share_one_sector_load() {
+		let pool = test_pool();
+		let mut connection = pool.get().unwrap();
+		let board = test_board(1, &mut connection);
+		let sectors = SectorCache::new(board.id, board.layout.count, board.layout.size);
+		let writers = 8;
+		let barrier = std::sync::Barrier::new(writers);
+
+		std::thread::scope(|scope| {
+			for writer in 0..writers {
+				let (sectors, barrier, pool) = (&sectors, &barrier, &pool);
+				scope.spawn(move || {
+					let mut connection = pool.get().unwrap();
+					barrier.wait();
+					let mut sector = sectors
+						.write_sector(0, &mut connection)
+						.unwrap()
+						.unwrap();
+					sector.colors[writer] = 1;
+				});
+			}
+		});
+
+		let colors = sectors
+			.read_sector(0, &mut connection)
+			.unwrap()
+			.colors
+			.clone();
+
+		board.delete(&mut connection).unwrap();
+
+		// a sector loaded again would have lost the earlier writes
+		assert!(colors[..writers].iter().all(|&color| color == 1));
+	}
+
+	#[test]
+	#[ignore = "needs a database"]
+	fn sectors_load_the_latest_placement_of_each_pixel() {
+		let pool = test_pool();
+		let mut connection = pool.get().unwrap();
+		let board = test_board(10, &mut connection);
+
+		// inserted out of order so that neither id nor insertion order alone
+		// gives the latest placement
+		let placements = [(1, 5), (0, 5), (1, 3)].map(|(color, timestamp)| {
+			model::NewPlacement {
+				board: board.id,
+				position: 0,
+				color,
+				timestamp,
+				user_id: None,
+			}
+		});
+		diesel::insert_into(schema::placement::table)
+			.values(&placements[..])
+			.execute(&mut connection)
+			.unwrap();
+
+		let sector = BoardSector::load(board.id, 0, &mut connection).unwrap();
+
+		board.delete(&mut connection).unwrap();
+
+		let sector = sector.unwrap();
+		assert_eq!(sector.colors[0], 0);
+		assert_eq!(&sector.timestamps[0..4], &5_u32.to_le_bytes());
+		assert_eq!(&sector.density[0..4], &3_u32.to_le_bytes());
+	}
+
+	#[tokio::test]
+	async fn placement_errors_respond_with_a_code() {
+		let errors = [
+			(PlaceError::Unplacable, StatusCode::FORBIDDEN, "unplacable"),
+			(PlaceError::InvalidColor, StatusCode::UNPROCESSABLE_ENTITY, "invalid_color"),
+			(PlaceError::NoOp, StatusCode::CONFLICT, "no_op"),
+			(PlaceError::Cooldown, StatusCode::TOO_MANY_REQUESTS, "cooldown"),
+			(PlaceError::OutOfBounds, StatusCode::NOT_FOUND, "out_of_bounds"),
+		];
+
+		for (error, status, code) in errors {
+			let response = error.into_response();
+			assert_eq!(response.status(), status);
+
+			let body = warp::hyper::body::to_bytes(response.into_body()).await.unwrap();
+			let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+			assert_eq!(body, serde_json::json!({ "code": code }));
+		}
+	}
+
+	#[test]
+	fn private_boards_are_hidden_from_unprivileged_users() {
+		let users = [
+			AuthedUser::None,
+			user_with(&[Permission::BoardsList, Permission::BoardsGet]),
+		];
+
+		for user in users {
+			assert!(!Visibility::Private.listed_for(&user));
+			assert!(!Visibility::Private.visible_to(&user));
+		}
+	}
+
+	#[test]
+	fn private_boards_are_shown_to_privileged_users() {
+		let user = user_with(&[Permission::BoardsPrivate]);
+
+		assert!(Visibility::Private.listed_for(&user));
+		assert!(Visibility::Private.visible_to(&user));
+	}
+
+	#[test]
+	fn unlisted_boards_are_reachable_but_not_listed() {
+		let user = AuthedUser::None;
+
+		assert!(!Visibility::Unlisted.listed_for(&user));
+		assert!(Visibility::Unlisted.visible_to(&user));
+		assert!(Visibility::Public.listed_for(&user));
+		assert!(Visibility::Public.visible_to(&user));
+	}
+
+	fn palette_of(size: u32) -> Palette {
+		(0..size)
+			.map(|index| {
+				(index, Color {
+					name: format!("Color {}", index),
+					value: index,
+					cooldown_multiplier: 1.0,
+				})
+			})
+			.collect()
+	}
+
+	#[test]
+	fn empty_palettes_are_invalid() {
+		let invalid = validate_palette(&Palette::new(), 256).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::EmptyPalette));
+		assert_eq!(invalid.field(), "palette");
+		assert!(validate_palette(&palette_of(2), 256).is_ok());
+	}
+
+	#[test]
+	fn palettes_larger_than_the_limit_are_invalid() {
+		let invalid = validate_palette(&palette_of(5), 4).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::PaletteTooLarge));
+		assert_eq!(invalid.field(), "palette");
+		assert!(validate_palette(&palette_of(4), 4).is_ok());
+	}
+
+	#[test]
+	fn color_indices_must_fit_in_a_byte() {
+		let mut palette = palette_of(2);
+		let color = palette.remove(&1).unwrap();
+		palette.insert(256, color);
+
+		let invalid = validate_palette(&palette, 256).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::ColorIndexOutOfRange));
+		assert_eq!(invalid.field(), "palette");
+		assert!(validate_palette(&palette_of(256), 256).is_ok());
+	}
+
+	fn tunables(settings: serde_json::Value) -> config::Tunables {
+		serde_json::from_value(settings).unwrap()
+	}
+
+	#[test]
+	fn pixels_become_available_along_the_cooldown_curve() {
+		let tunables = tunables(serde_json::json!({ "cooldown_curve": [5, 10, 20] }));
+
+		assert_eq!(cooldown_offsets(&tunables, None, 0, 5), vec![5, 15, 35, 55, 75]);
+	}
+
+	#[test]
+	fn cooldowns_default_to_thirty_seconds_a_pixel() {
+		let tunables = tunables(serde_json::json!({}));
+
+		assert_eq!(cooldown_offsets(&tunables, None, 0, 3), vec![30, 60, 90]);
+	}
+
+	#[test]
+	fn board_cooldowns_override_the_curve() {
+		let tunables = tunables(serde_json::json!({
+			"cooldown_curve": [5, 10],
+			"cooldown_density_penalty": 1,
+		}));
+
+		assert_eq!(cooldown_offsets(&tunables, Some(60), 0, 3), vec![60, 120, 180]);
+		assert_eq!(cooldown_offsets(&tunables, Some(60), 4, 2), vec![64, 128]);
+	}
+
+	#[test]
+	fn cooldown_offsets_saturate() {
+		let tunables = tunables(serde_json::json!({ "cooldown_density_penalty": u32::MAX }));
+
+		assert_eq!(cooldown_offsets(&tunables, Some(u32::MAX), 0, 2), vec![u32::MAX; 2]);
+		assert_eq!(cooldown_offsets(&tunables, Some(1), 2, 2), vec![u32::MAX; 2]);
+	}
+
+	#[test]
+	fn density_adds_to_each_pixel_cooldown() {
+		let tunables = tunables(serde_json::json!({
+			"cooldown": 30,
+			"cooldown_density_penalty": 5,
+		}));
+
+		assert_eq!(cooldown_offsets(&tunables, None, 0, 3), vec![30, 60, 90]);
+		assert_eq!(cooldown_offsets(&tunables, None, 2, 3), vec![40, 80, 120]);
+	}
+
+	#[test]
+	fn density_is_ignored_without_a_penalty() {
+		let tunables = tunables(serde_json::json!({ "cooldown": 30 }));
+
+		assert_eq!(cooldown_offsets(&tunables, None, 100, 2), vec![30, 60]);
+	}
+
+	#[test]
+	fn palette_patches_change_only_their_entries() {
+		let patch: PalettePatch = serde_json::from_str(r#"{
+			"0": { "name": "Renamed" },
+			"1": null,
+			"4": { "name": "New", "value": 16777215 }
+		}"#).unwrap();
+
+		let palette = patched_palette(&palette_of(3), &patch).unwrap();
+
+		let mut indices = palette.keys().copied().collect::<Vec<_>>();
+		indices.sort_unstable();
+		assert_eq!(indices, vec![0, 2, 4]);
+		assert_eq!(palette[&0].name, "Renamed");
+		assert_eq!(palette[&0].value, 0);
+		assert_eq!(palette[&2].name, "Color 2");
+		assert_eq!(palette[&4].value, 16777215);
+	}
+
+	#[test]
+	fn palette_patches_need_complete_new_colors() {
+		let patch: PalettePatch = serde_json::from_str(r#"{
+			"7": { "name": "Nameless" }
+		}"#).unwrap();
+
+		let invalid = patched_palette(&palette_of(3), &patch).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::IncompleteColor));
+		assert_eq!(invalid.field(), "palette");
+	}
+
+	#[test]
+	fn degenerate_shapes_are_invalid() {
+		let shapes: [VecShape; 4] = [
+			vec![],
+			vec![vec![]],
+			vec![vec![1, 1], vec![]],
+			vec![vec![2, 0]],
+		];
+
+		for shape in shapes {
+			let invalid = validate_shape(&shape, usize::MAX).unwrap_err();
+			assert!(matches!(invalid, InvalidField::DegenerateShape), "{:?}", shape);
+			assert_eq!(invalid.field(), "shape");
+		}
+
+		assert!(validate_shape(&vec![vec![1, 1], vec![4, 4]], usize::MAX).is_ok());
+	}
+
+	#[test]
+	fn shapes_larger_than_the_limit_are_invalid() {
+		let shape = vec![vec![2, 2], vec![8, 8]];
+
+		let invalid = validate_shape(&shape, 255).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::ShapeTooLarge));
+		assert_eq!(invalid.field(), "shape");
+		assert!(validate_shape(&shape, 256).is_ok());
+	}
+
+	#[test]
+	fn overflowing_shapes_are_invalid() {
+		let shape = vec![vec![usize::MAX, 2]];
+
+		let invalid = validate_shape(&shape, usize::MAX).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::ShapeTooLarge));
+	}
+
+	#[test]
+	fn placement_caps_must_allow_a_placement() {
+		let invalid = validate_placement_cap(0).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::NoPlacementsAllowed));
+		assert_eq!(invalid.field(), "placement_cap");
+		assert!(validate_placement_cap(1).is_ok());
+	}
+
+	#[test]
+	fn cooldowns_must_fit_every_pixel_in_a_timestamp() {
+		let invalid = validate_cooldown(4_000_000_000, 1).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::CooldownTooLong));
+		assert_eq!(invalid.field(), "cooldown");
+		assert!(validate_cooldown(1_000_000, 3000).is_err());
+		assert!(validate_cooldown(60, 100).is_ok());
+	}
+
+	#[test]
+	fn boards_need_pixels_available() {
+		let invalid = validate_max_pixels_available(0).unwrap_err();
+
+		assert!(matches!(invalid, InvalidField::NoPixelsAvailable));
+		assert_eq!(invalid.field(), "max_pixels_available");
+		assert!(validate_max_pixels_available(1).is_ok());
+	}
+
+	#[test]
+	fn duplicate_palette_indices_are_rejected() {
+		let post: BoardInfoPost = serde_json::from_str(r#"{
+			"name": "Board",
+			"shape": [[4, 4]],
+			"palette": {
+				"0": { "name": "Black", "value": 0 },
+				"3": { "name": "White", "value": 16777215 },
+				"3": { "name": "Red", "value": 16711680 }
+			},
+			"max_pixels_available": 6
+		}"#).unwrap();
+
+		let DuplicateColorIndices(indices) = post.check_color_indices().unwrap_err();
+		assert_eq!(indices, vec![3]);
+
+		let patch: BoardInfoPatch = serde_json::from_str(r#"{
+			"palette": {
+				"1": { "name": "Black", "value": 0 },
+				"1": { "name": "White", "value": 16777215 }
+			}
+		}"#).unwrap();
+
+		let DuplicateColorIndices(indices) = patch.check_color_indices().unwrap_err();
+		assert_eq!(indices, vec![1]);
+
+		let patch: BoardInfoPatch = serde_json::from_str(r#"{ "name": "Renamed" }"#).unwrap();
+		assert!(patch.check_color_indices().is_ok());
+	}
+
+	#[test]
+	fn pngs_are_indexed_with_the_palette() {
+		let palette = Palette::from([
+			(0, Color {
+				name: "Red".to_owned(),
+				value: 0xff0000ff,
+				cooldown_multiplier: 1.0,
+			}),
+			(2, Color {
+				name: "Translucent Green".to_owned(),
+				value: 0x00ff0080,
+				cooldown_multiplier: 1.0,
+			}),
+		]);
+		let indices = [[0, 2, 0], [2, 2, 0]];
+
+		let png = encode_png(3, 2, &palette, |y, row| {
+			row.copy_from_slice(&indices[y]);
+			Ok(())
+		})
+		.unwrap();
+
+		let mut decoder = png::Decoder::new(png.as_slice());
+		decoder.set_transformations(png::Transformations::IDENTITY);
+		let mut reader = decoder.read_info().unwrap();
+
+		let info = reader.info();
+		assert_eq!((info.width, info.height), (3, 2));
+		assert_eq!(info.color_type, png::ColorType::Indexed);
+		let colors = info.palette.as_deref().unwrap();
+		assert_eq!(&colors[0..3], &[0xff, 0x00, 0x00]);
+		assert_eq!(&colors[6..9], &[0x00, 0xff, 0x00]);
+		let transparency = info.trns.as_deref().unwrap();
+		assert_eq!(transparency[0], 0xff);
+		assert_eq!(transparency[2], 0x80);
+
+		let mut pixels = vec![0; reader.output_buffer_size()];
+		reader.next_frame(&mut pixels).unwrap();
+		assert_eq!(&pixels[..6], &[0, 2, 0, 2, 2, 0]);
+	}
+
+	#[test]
+	fn png_errors_are_returned() {
+		let result = encode_png(1, 1, &Palette::new(), |_, _| {
+			Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no colors"))
+		});
+
+		assert!(result.is_err());
+	}
+
+	#[test]
+	fn colors_are_counted_across_sectors() {
+		// two sectors of four pixels
+		let colors: &[u8] = &[0, 1, 1, 2, 1, 0, 3, 1];
+
+		for chunk_size in [1, 3, 4, 8, 16] {
+			let counts = count_colors(&mut &*colors, colors.len(), chunk_size).unwrap();
+
+			assert_eq!(counts[0], 2);
+			assert_eq!(counts[1], 4);
+			assert_eq!(counts[2], 1);
+			assert_eq!(counts[3], 1);
+			assert_eq!(counts.iter().sum::<usize>(), colors.len());
+		}
+	}
+
+	#[test]
+	fn colors_are_only_counted_within_the_length() {
+		let colors: &[u8] = &[5, 5, 5, 5];
+
+		let counts = count_colors(&mut &*colors, 2, 4).unwrap();
+
+		assert_eq!(counts[5], 2);
+	}
+
+	#[test]
+	fn counting_colors_fails_on_short_data() {
+		let colors: &[u8] = &[1, 2];
+
+		assert!(count_colors(&mut &*colors, 4, 4).is_err());
+	}
+}
@@ -12,6 +12,7 @@ pub enum SectorBuffer {
 	Timestamps,
 	Initial,
 	Mask,
+	Density,
 }
 
 pub struct BoardSector {
@@ -21,7 +22,8 @@ pub struct BoardSector {
 	pub timestamps: BytesMut,
 	pub mask: BytesMut,
 	pub initial: BytesMut,
-	// TODO: maybe a density buffer for how many placements there have been
+	/// The number of placements at each position as little-endian u32s.
+	pub density: BytesMut,
 }
 
 impl BoardSector {
@@ -73,8 +75,11 @@ impl BoardSector {
 		buffer: Option<&SectorBuffer>,
 	) -> QueryResult<()> {
 		match buffer {
-			Some(SectorBuffer::Colors) => unimplemented!(),
-			Some(SectorBuffer::Timestamps) => unimplemented!(),
+			// These are derived from placements when loading and aren't
+			// stored with the sector so there's nothing to save.
+			Some(SectorBuffer::Colors) => Ok(()),
+			Some(SectorBuffer::Timestamps) => Ok(()),
+			Some(SectorBuffer::Density) => Ok(()),
 			Some(SectorBuffer::Initial) => {
 				diesel::update(schema::board_sector::table)
 					.set(schema::board_sector::initial.eq(&*self.initial))
@@ -114,6 +119,15 @@ impl BoardSector {
 		}
 	}
 
+	/// The number of bytes held by the sector's buffers.
+	pub fn memory_size(&self) -> usize {
+		self.colors.len()
+			+ self.timestamps.len()
+			+ self.mask.len()
+			+ self.initial.len()
+			+ self.density.len()
+	}
+
 	fn from_model(
 		sector: model::BoardSector,
 		connection: &mut Connection,
@@ -126,20 +140,21 @@ impl BoardSector {
 		let mask = BytesMut::from(&*sector.mask);
 		let mut colors = initial.clone();
 		let mut timestamps = BytesMut::from(&vec![0; sector_size * 4][..]);
+		let mut density = BytesMut::from(&vec![0; sector_size * 4][..]);
 
 		let start_position = sector_size as i64 * sector.index as i64;
 		let end_position = start_position + sector_size as i64 - 1;
 
 		// TODO: maybe this will be possible in qsl one day…
-		// until then, maybe there's a non-nested way to do this.
+		// NOTE: DISTINCT ON keeps the first row of each position only with
+		// respect to the query's own ORDER BY; a subquery's order isn't
+		// guaranteed to survive, so the ordering must be here.
 		let placements = diesel::sql_query(
 			"
-			SELECT DISTINCT ON (position) * FROM (
-				SELECT * FROM placement
-				WHERE board = $1
-				AND position BETWEEN $2 AND $3
-				ORDER BY timestamp DESC, id DESC
-			) AS ordered",
+			SELECT DISTINCT ON (position) * FROM placement
+			WHERE board = $1
+			AND position BETWEEN $2 AND $3
+			ORDER BY position, timestamp DESC, id DESC",
 		)
 		.bind::<diesel::sql_types::Int4, _>(sector.board)
 		.bind::<diesel::sql_types::Int8, _>(start_position)
@@ -147,12 +162,37 @@ impl BoardSector {
 		.load::<model::Placement>(connection)?;
 
 		for placement in placements {
-			let index = placement.position as usize;
+			let index = (placement.position - start_position) as usize;
 			colors[index] = placement.color as u8;
 			let mut timestamp_slice = &mut timestamps[index * 4..index * 4 + 4];
 			timestamp_slice.put_u32_le(placement.timestamp as u32);
 		}
 
+		#[derive(QueryableByName)]
+		struct PositionCount {
+			#[sql_type = "diesel::sql_types::Int8"]
+			position: i64,
+			#[sql_type = "diesel::sql_types::Int8"]
+			count: i64,
+		}
+
+		let counts = diesel::sql_query(
+			"SELECT position, COUNT(*) AS count FROM placement
+			WHERE board = $1
+			AND position BETWEEN $2 AND $3
+			GROUP BY position",
+		)
+		.bind::<diesel::sql_types::Int4, _>(sector.board)
+		.bind::<diesel::sql_types::Int8, _>(start_position)
+		.bind::<diesel::sql_types::Int8, _>(end_position)
+		.load::<PositionCount>(connection)?;
+
+		for PositionCount { position, count } in counts {
+			let index = (position - start_position) as usize;
+			let mut density_slice = &mut density[index * 4..index * 4 + 4];
+			density_slice.put_u32_le(count as u32);
+		}
+
 		Ok(Self {
 			board,
 			index,
@@ -160,6 +200,7 @@ impl BoardSector {
 			mask,
 			colors,
 			timestamps,
+			density,
 		})
 	}
 }
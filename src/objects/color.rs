@@ -1,16 +1,37 @@
 use std::collections::HashMap;
 
-use diesel::{prelude::*, Connection as DConnection};
+use diesel::{
+	prelude::*,
+	result::{DatabaseErrorKind, Error as DieselError},
+	upsert::excluded,
+	Connection as DConnection,
+};
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, Map};
 
 use crate::database::{model, schema, Connection};
 
 pub type Palette = HashMap<u32, Color>;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Color {
 	pub name: String,
 	pub value: u32,
+	/// How much more placing this color costs than a normal color.
+	#[serde(default = "Color::default_cooldown_multiplier")]
+	pub cooldown_multiplier: f32,
+}
+
+impl Color {
+	fn default_cooldown_multiplier() -> f32 {
+		1.0
+	}
+
+	/// The number of available pixels a placement of this color consumes.
+	/// Fractional multipliers are rounded up.
+	pub fn cost(&self) -> usize {
+		self.cooldown_multiplier.ceil().max(1.0) as usize
+	}
 }
 
 impl From<model::Color> for Color {
@@ -18,30 +39,334 @@ impl From<model::Color> for Color {
 		Color {
 			name: color.name,
 			value: color.value as u32,
+			cooldown_multiplier: color.cooldown_multiplier,
 		}
 	}
 }
 
+/// Changes to a single palette entry. Absent fields are left unchanged.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ColorPatch {
+	pub name: Option<String>,
+	pub value: Option<u32>,
+	pub cooldown_multiplier: Option<f32>,
+}
+
+impl ColorPatch {
+	/// Apply the patch to an existing color, or create a new one if there is
+	/// none. New colors need at least a name and value.
+	pub fn apply(
+		&self,
+		color: Option<&Color>,
+	) -> Option<Color> {
+		match color {
+			Some(color) => {
+				Some(Color {
+					name: self.name.clone().unwrap_or_else(|| color.name.clone()),
+					value: self.value.unwrap_or(color.value),
+					cooldown_multiplier: self
+						.cooldown_multiplier
+						.unwrap_or(color.cooldown_multiplier),
+				})
+			},
+			None => {
+				Some(Color {
+					name: self.name.clone()?,
+					value: self.value?,
+					cooldown_multiplier: self
+						.cooldown_multiplier
+						.unwrap_or_else(Color::default_cooldown_multiplier),
+				})
+			},
+		}
+	}
+}
+
+/// Changes to palette entries by index. A null entry removes that index.
+pub type PalettePatch = HashMap<u32, Option<ColorPatch>>;
+
+/// A palette as sent in a request.
+/// Plain maps silently keep the last entry for a repeated index, so every
+/// entry is kept here until the indices have been checked.
+#[serde_as]
+#[derive(Deserialize, Debug, Clone)]
+#[serde(transparent)]
+pub struct PaletteEntries(#[serde_as(as = "Map<_, _>")] Vec<(u32, Color)>);
+
+impl PaletteEntries {
+	/// The indices which appear more than once, sorted.
+	pub fn duplicate_indices(&self) -> Vec<u32> {
+		let mut indices = self.0
+			.iter()
+			.map(|(index, _)| *index)
+			.collect::<Vec<_>>();
+		indices.sort_unstable();
+
+		let mut duplicates = indices
+			.windows(2)
+			.filter(|pair| pair[0] == pair[1])
+			.map(|pair| pair[0])
+			.collect::<Vec<_>>();
+		duplicates.dedup();
+
+		duplicates
+	}
+
+	/// The palette described by the entries, keeping the last of any
+	/// repeated index.
+	pub fn to_palette(&self) -> Palette {
+		self.0.iter().cloned().collect()
+	}
+}
+
+/// The values which appear on more than one color in the palette, sorted.
+pub fn duplicate_values(palette: &Palette) -> Vec<u32> {
+	let mut values = palette
+		.values()
+		.map(|color| color.value)
+		.collect::<Vec<_>>();
+	values.sort_unstable();
+
+	let mut duplicates = values
+		.windows(2)
+		.filter(|pair| pair[0] == pair[1])
+		.map(|pair| pair[0])
+		.collect::<Vec<_>>();
+	duplicates.dedup();
+
+	duplicates
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+	/// Colors which would be removed but are still used by placements.
+	ColorsInUse(Vec<u32>),
+	DatabaseError(DieselError),
+}
+
+impl From<DieselError> for PaletteError {
+	fn from(error: DieselError) -> Self {
+		Self::DatabaseError(error)
+	}
+}
+
+fn colors_in_use(
+	palette: &Palette,
+	board_id: i32,
+	connection: &mut Connection,
+) -> QueryResult<Vec<u32>> {
+	let mut in_use = schema::placement::table
+		.select(schema::placement::color)
+		.filter(schema::placement::board.eq(board_id))
+		.distinct()
+		.load::<i16>(connection)?
+		.into_iter()
+		.map(|color| color as u32)
+		.filter(|color| !palette.contains_key(color))
+		.collect::<Vec<_>>();
+
+	in_use.sort_unstable();
+
+	Ok(in_use)
+}
+
 pub fn replace_palette(
 	palette: &Palette,
 	board_id: i32,
 	connection: &mut Connection,
-) -> QueryResult<()> {
+) -> Result<(), PaletteError> {
 	connection.transaction(|connection| {
+		let in_use = colors_in_use(palette, board_id, connection)?;
+		if !in_use.is_empty() {
+			return Err(PaletteError::ColorsInUse(in_use));
+		}
+
+		let indices = palette
+			.keys()
+			.map(|index| *index as i32)
+			.collect::<Vec<_>>();
+
+		// NOTE: colors are only deleted if they're not in the new palette since
+		// placements reference them.
 		diesel::delete(schema::color::table)
-			.filter(schema::color::board.eq(board_id))
-			.execute(connection)?;
-
-		for (index, Color { name, value }) in palette {
-			diesel::insert_into(schema::color::table)
-				.values(model::Color {
-					board: board_id,
-					index: *index as i32,
-					name: name.clone(),
-					value: *value as i32,
-				})
-				.execute(connection)?;
+			.filter(
+				schema::color::board
+					.eq(board_id)
+					.and(schema::color::index.ne_all(indices)),
+			)
+			.execute(connection)
+			.map_err(removal_error)?;
+
+		for (index, color) in palette {
+			upsert_color(*index, color, board_id, connection)?;
 		}
 		Ok(())
 	})
 }
+
+/// Apply per-index changes to a board's stored palette.
+/// Only the changed colors are touched; `palette` is the resulting palette.
+/// If `remap_to` is set, placements of removed colors are changed to it and
+/// the positions of those placements are returned.
+pub fn patch_palette(
+	patch: &PalettePatch,
+	palette: &Palette,
+	remap_to: Option<u32>,
+	board_id: i32,
+	connection: &mut Connection,
+) -> Result<Vec<u64>, PaletteError> {
+	connection.transaction(|connection| {
+		let removed = patch
+			.iter()
+			.filter(|(_, color)| color.is_none())
+			.map(|(index, _)| *index as i32)
+			.collect::<Vec<_>>();
+
+		let mut remapped = vec![];
+
+		if !removed.is_empty() {
+			if let Some(remap_to) = remap_to {
+				let removed = removed
+					.iter()
+					.map(|index| *index as i16)
+					.collect::<Vec<_>>();
+
+				remapped = diesel::update(schema::placement::table)
+					.filter(
+						schema::placement::board
+							.eq(board_id)
+							.and(schema::placement::color.eq_any(removed)),
+					)
+					.set(schema::placement::color.eq(remap_to as i16))
+					.returning(schema::placement::position)
+					.get_results::<i64>(connection)?
+					.into_iter()
+					.map(|position| position as u64)
+					.collect::<Vec<_>>();
+
+				remapped.sort_unstable();
+				remapped.dedup();
+			}
+
+			let in_use = colors_in_use(palette, board_id, connection)?;
+			if !in_use.is_empty() {
+				return Err(PaletteError::ColorsInUse(in_use));
+			}
+
+			diesel::delete(schema::color::table)
+				.filter(
+					schema::color::board
+						.eq(board_id)
+						.and(schema::color::index.eq_any(removed)),
+				)
+				.execute(connection)
+				.map_err(removal_error)?;
+		}
+
+		for index in patch.iter().filter(|(_, color)| color.is_some()).map(|(index, _)| index) {
+			upsert_color(*index, &palette[index], board_id, connection)?;
+		}
+		Ok(remapped)
+	})
+}
+
+fn removal_error(error: DieselError) -> PaletteError {
+	match error {
+		// A placement was made with a removed color since checking.
+		// The transaction is aborted at this point so we can't
+		// query which one, but it's still a conflict.
+		DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+			PaletteError::ColorsInUse(vec![])
+		},
+		error => PaletteError::DatabaseError(error),
+	}
+}
+
+fn upsert_color(
+	index: u32,
+	Color { name, value, cooldown_multiplier }: &Color,
+	board_id: i32,
+	connection: &mut Connection,
+) -> QueryResult<usize> {
+	diesel::insert_into(schema::color::table)
+		.values(model::Color {
+			board: board_id,
+			index: index as i32,
+			name: name.clone(),
+			value: *value as i32,
+			cooldown_multiplier: *cooldown_multiplier,
+		})
+		.on_conflict((schema::color::board, schema::color::index))
+		.do_update()
+		.set((
+			schema::color::name.eq(excluded(schema::color::name)),
+			schema::color::value.eq(excluded(schema::color::value)),
+			schema::color::cooldown_multiplier.eq(excluded(schema::color::cooldown_multiplier)),
+		))
+		.execute(connection)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn repeated_indices_are_kept_and_reported() {
+		let entries: PaletteEntries = serde_json::from_str(r#"{
+			"0": { "name": "Black", "value": 0 },
+			"1": { "name": "White", "value": 16777215 },
+			"0": { "name": "Red", "value": 16711680 },
+			"2": { "name": "Green", "value": 65280 },
+			"1": { "name": "Blue", "value": 255 },
+			"0": { "name": "Grey", "value": 8421504 }
+		}"#).unwrap();
+
+		assert_eq!(entries.duplicate_indices(), vec![0, 1]);
+	}
+
+	#[test]
+	fn color_patches_keep_unchanged_fields() {
+		let color = Color {
+			name: "Black".to_owned(),
+			value: 0,
+			cooldown_multiplier: 2.0,
+		};
+		let patch: ColorPatch = serde_json::from_str(r#"{ "value": 1 }"#).unwrap();
+
+		let patched = patch.apply(Some(&color)).unwrap();
+
+		assert_eq!(patched.name, "Black");
+		assert_eq!(patched.value, 1);
+		assert_eq!(patched.cooldown_multiplier, 2.0);
+	}
+
+	#[test]
+	fn color_patches_create_complete_colors() {
+		let patch: ColorPatch = serde_json::from_str(r#"{
+			"name": "White",
+			"value": 16777215
+		}"#).unwrap();
+
+		let created = patch.apply(None).unwrap();
+
+		assert_eq!(created.name, "White");
+		assert_eq!(created.cooldown_multiplier, Color::default_cooldown_multiplier());
+
+		let patch: ColorPatch = serde_json::from_str(r#"{ "name": "White" }"#).unwrap();
+		assert!(patch.apply(None).is_none());
+	}
+
+	#[test]
+	fn unique_indices_are_not_reported() {
+		let entries: PaletteEntries = serde_json::from_str(r#"{
+			"0": { "name": "Black", "value": 0 },
+			"1": { "name": "White", "value": 16777215 }
+		}"#).unwrap();
+
+		assert!(entries.duplicate_indices().is_empty());
+
+		let palette = entries.to_palette();
+		assert_eq!(palette.len(), 2);
+		assert_eq!(palette[&1].name, "White");
+	}
+}
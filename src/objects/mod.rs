@@ -11,14 +11,16 @@ pub mod socket;
 pub mod user;
 pub mod user_count;
 
-pub use board::{Board, BoardInfo, BoardInfoPatch, BoardInfoPost, MaskValue};
+pub use board::{
+	Board, BoardInfo, BoardInfoPatch, BoardInfoPost, MaskValue, ShapeChange, Visibility,
+};
 pub use board_sector::{BoardSector, SectorBuffer};
-pub use color::{Color, Palette};
+pub use color::{Color, Palette, PalettePatch};
 pub use paginated_list::{Page, PageToken, PaginationOptions};
-pub use placement::PlacementRequest;
+pub use placement::{DatedPlacement, PlacementFilter, PlacementRequest, TimestampOptions};
 pub use reference::Reference;
 pub use sector_cache::{SectorCache, SectorCacheAccess};
-pub use shape::{Shape, VecShape};
+pub use shape::{SectorLayout, Shape, VecShape};
 pub use socket::{AuthedSocket, Extension, UnauthedSocket};
 pub use user::{AuthedUser, User};
 pub use user_count::UserCount;
@@ -1,7 +1,9 @@
+use std::ops::Range;
+
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
-use crate::objects::{Extension, Palette, VecShape};
+use crate::objects::{Extension, Palette, VecShape, Visibility};
 
 pub mod server {
 	use super::*;
@@ -12,6 +14,46 @@ pub mod server {
 		pub values: Vec<T>,
 	}
 
+	impl<T: Clone> Change<T> {
+		/// The parts of this change which fall within a region.
+		fn within(
+			&self,
+			region: &[Range<u64>],
+		) -> Vec<Self> {
+			let end = self.position + self.values.len() as u64;
+			region
+				.iter()
+				.filter_map(|range| {
+					let start = range.start.max(self.position);
+					let end = range.end.min(end);
+					(start < end).then(|| {
+						let offset = (start - self.position) as usize;
+						let length = (end - start) as usize;
+						Self {
+							position: start,
+							values: self.values[offset..offset + length].to_vec(),
+						}
+					})
+				})
+				.collect()
+		}
+	}
+
+	fn changes_within<T: Clone>(
+		changes: &Option<Vec<Change<T>>>,
+		region: &[Range<u64>],
+	) -> Option<Vec<Change<T>>> {
+		changes
+			.as_ref()
+			.map(|changes| {
+				changes
+					.iter()
+					.flat_map(|change| change.within(region))
+					.collect::<Vec<_>>()
+			})
+			.filter(|changes| !changes.is_empty())
+	}
+
 	#[skip_serializing_none]
 	#[derive(Serialize, Debug, Clone)]
 	pub struct BoardInfo {
@@ -19,6 +61,12 @@ pub mod server {
 		pub shape: Option<VecShape>,
 		pub palette: Option<Palette>,
 		pub max_pixels_available: Option<u32>,
+		pub visibility: Option<Visibility>,
+		/// None if unchanged, Some(None) if the cap was removed.
+		pub placement_cap: Option<Option<u32>>,
+		/// None if unchanged, Some(None) if the override was removed.
+		pub cooldown: Option<Option<u32>>,
+		pub frozen: Option<bool>,
 	}
 
 	#[skip_serializing_none]
@@ -34,6 +82,7 @@ pub mod server {
 	#[derive(Serialize, Debug, Clone)]
 	#[serde(tag = "type")]
 	#[serde(rename_all = "kebab-case")]
+	#[allow(clippy::large_enum_variant)]
 	pub enum Packet {
 		BoardUpdate {
 			info: Option<BoardInfo>,
@@ -43,14 +92,66 @@ pub mod server {
 			count: u32,
 			next: Option<u64>,
 		},
+		UserCount {
+			active: usize,
+			idle_timeout: u32,
+		},
+		/// Changes since the socket's `since` time are unavailable and the
+		/// client should fetch the board data again.
+		Refetch,
 		Ready,
 	}
 
+	impl Packet {
+		/// Restrict any board data in this packet to a region.
+		/// Returns None if nothing in the packet is relevant to the region.
+		pub fn within(
+			&self,
+			region: &[Range<u64>],
+		) -> Option<Self> {
+			match self {
+				Self::BoardUpdate {
+					info,
+					data: Some(data),
+				} => {
+					let data = BoardData {
+						colors: changes_within(&data.colors, region),
+						timestamps: changes_within(&data.timestamps, region),
+						initial: changes_within(&data.initial, region),
+						mask: changes_within(&data.mask, region),
+					};
+
+					let data = if data.colors.is_none()
+						&& data.timestamps.is_none()
+						&& data.initial.is_none()
+						&& data.mask.is_none()
+					{
+						None
+					} else {
+						Some(data)
+					};
+
+					if info.is_none() && data.is_none() {
+						None
+					} else {
+						Some(Self::BoardUpdate {
+							info: info.clone(),
+							data,
+						})
+					}
+				},
+				packet => Some(packet.clone()),
+			}
+		}
+	}
+
 	impl From<&Packet> for Extension {
 		fn from(event: &Packet) -> Self {
 			match event {
 				Packet::BoardUpdate { .. } => Extension::Core,
 				Packet::PixelsAvailable { .. } => Extension::Core,
+				Packet::UserCount { .. } => Extension::Core,
+				Packet::Refetch => Extension::Core,
 				Packet::Ready => Extension::Core,
 			}
 		}
@@ -18,6 +18,18 @@ pub struct PaginationOptions<T> {
 	pub limit: Option<usize>,
 }
 
+impl<T> PaginationOptions<T> {
+	const DEFAULT_LIMIT: usize = 10;
+	const MAX_LIMIT: usize = 100;
+
+	/// The number of items per page, clamped to the range pages are served in.
+	pub fn limit(&self) -> usize {
+		self.limit
+			.unwrap_or(Self::DEFAULT_LIMIT)
+			.clamp(1, Self::MAX_LIMIT)
+	}
+}
+
 pub struct PageToken {
 	pub id: usize,
 	pub timestamp: u32,
@@ -80,3 +92,43 @@ impl<'de> Deserialize<'de> for PageToken {
 		deserializer.deserialize_str(PageVisitor)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn options(limit: Option<usize>) -> PaginationOptions<PageToken> {
+		PaginationOptions { page: None, limit }
+	}
+
+	#[test]
+	fn limits_default_to_ten() {
+		assert_eq!(options(None).limit(), 10);
+	}
+
+	#[test]
+	fn limits_are_clamped() {
+		assert_eq!(options(Some(0)).limit(), 1);
+		assert_eq!(options(Some(50)).limit(), 50);
+		assert_eq!(options(Some(1000)).limit(), 100);
+	}
+
+	#[test]
+	fn page_tokens_are_parsed() {
+		let options: PaginationOptions<PageToken> =
+			serde_qs::from_str("page=120_7&limit=20").unwrap();
+
+		let page = options.page.as_ref().unwrap();
+		assert_eq!((page.timestamp, page.id), (120, 7));
+		assert_eq!(options.limit(), 20);
+	}
+
+	#[test]
+	fn malformed_page_tokens_are_rejected() {
+		for query in ["page=120", "page=a_7", "page=120_b", "limit=many"] {
+			let options = serde_qs::from_str::<PaginationOptions<PageToken>>(query);
+
+			assert!(options.is_err(), "{}", query);
+		}
+	}
+}
@@ -1,6 +1,167 @@
-use serde::Deserialize;
+use std::time::SystemTime;
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_with::{formats::CommaSeparator, serde_as, StringWithSeparator};
+
+use crate::database::model;
 
 #[derive(Deserialize, Debug)]
 pub struct PlacementRequest {
 	pub color: u8,
 }
+
+/// Whether responses should also give timestamps as RFC 3339 dates.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+pub struct TimestampOptions {
+	#[serde(default)]
+	pub rfc3339: bool,
+}
+
+impl TimestampOptions {
+	/// A time as an RFC 3339 date if requested.
+	pub fn format(
+		&self,
+		time: SystemTime,
+	) -> Option<String> {
+		self.rfc3339
+			.then(|| humantime::format_rfc3339_seconds(time).to_string())
+	}
+
+	/// The options as a query parameter prefixed with an ampersand.
+	pub fn to_query(self) -> &'static str {
+		if self.rfc3339 {
+			"&rfc3339=true"
+		} else {
+			""
+		}
+	}
+}
+
+/// A placement with its board-relative timestamp optionally also given as an
+/// RFC 3339 date.
+#[derive(Serialize, Debug)]
+pub struct DatedPlacement<'l> {
+	#[serde(flatten)]
+	pub placement: &'l model::Placement,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub timestamp_rfc3339: Option<String>,
+}
+
+#[serde_as]
+#[derive(Deserialize, Debug, Default)]
+pub struct PlacementFilter {
+	/// Only include placements of these colors, given as a comma separated list.
+	#[serde_as(as = "Option<StringWithSeparator::<CommaSeparator, u8>>")]
+	#[serde(default)]
+	pub colors: Option<Vec<u8>>,
+	/// Only include placements by this user, given as a user URI.
+	#[serde(default, deserialize_with = "user_uri")]
+	pub user: Option<String>,
+}
+
+const USER_URI_PREFIX: &str = "/users/";
+
+/// Parse a user id from a `/users/{id}` URI.
+fn user_uri<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let uri = String::deserialize(deserializer)?;
+	let id = uri
+		.strip_prefix(USER_URI_PREFIX)
+		.filter(|id| !id.is_empty() && !id.contains('/'))
+		.ok_or_else(|| de::Error::custom("expected a user uri"))?;
+
+	percent_decode_str(id)
+		.decode_utf8()
+		.map(|id| Some(id.into_owned()))
+		.map_err(de::Error::custom)
+}
+
+impl PlacementFilter {
+	/// The filter as query parameters, each prefixed with an ampersand.
+	pub fn to_query(&self) -> String {
+		let mut query = String::new();
+
+		if let Some(ref colors) = self.colors {
+			let colors = colors
+				.iter()
+				.map(u8::to_string)
+				.collect::<Vec<_>>()
+				.join(",");
+			query.push_str(&format!("&colors={}", colors));
+		}
+
+		if let Some(ref user) = self.user {
+			// the uri is encoded twice: once as a path segment and again as
+			// a query value.
+			let uri = format!(
+				"{}{}",
+				USER_URI_PREFIX,
+				utf8_percent_encode(user, NON_ALPHANUMERIC),
+			);
+			query.push_str(&format!(
+				"&user={}",
+				utf8_percent_encode(&uri, NON_ALPHANUMERIC),
+			));
+		}
+
+		query
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Parse a query string the same way the routes do.
+	async fn parse(query: &str) -> Result<PlacementFilter, warp::Rejection> {
+		warp::test::request()
+			.path(&format!("/?{}", query))
+			.filter(&warp::query::<PlacementFilter>())
+			.await
+	}
+
+	#[tokio::test]
+	async fn colors_are_parsed_from_a_list() {
+		let filter = parse("colors=3,7,12").await.unwrap();
+
+		assert_eq!(filter.colors, Some(vec![3, 7, 12]));
+		assert_eq!(filter.user, None);
+	}
+
+	#[tokio::test]
+	async fn colors_are_optional() {
+		let filter = parse("").await.unwrap();
+
+		assert_eq!(filter.colors, None);
+		assert_eq!(filter.to_query(), "");
+	}
+
+	#[tokio::test]
+	async fn invalid_colors_are_rejected() {
+		assert!(parse("colors=3,red").await.is_err());
+		assert!(parse("colors=256").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn users_are_parsed_from_a_uri() {
+		let filter = parse("user=%2Fusers%2Fsome%2520one").await.unwrap();
+
+		assert_eq!(filter.user.as_deref(), Some("some one"));
+		assert!(parse("user=someone").await.is_err());
+		assert!(parse("user=%2Fusers%2F").await.is_err());
+		assert!(parse("user=%2Fusers%2Fa%2Fb").await.is_err());
+	}
+
+	#[tokio::test]
+	async fn filters_are_kept_in_page_links() {
+		let filter = parse("colors=3,7,12&user=%2Fusers%2Fsome%2520one").await.unwrap();
+		let query = filter.to_query();
+
+		let reparsed = parse(query.trim_start_matches('&')).await.unwrap();
+		assert_eq!(reparsed.colors, filter.colors);
+		assert_eq!(reparsed.user, filter.user);
+	}
+}
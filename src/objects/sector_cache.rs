@@ -31,17 +31,25 @@ impl SectorCache {
 		}
 	}
 
+	/// Load a sector into the cache if it isn't already.
 	fn fill_sector(
 		&self,
 		sector_index: usize,
 		connection: &mut Connection,
-	) -> QueryResult<RwLockWriteGuard<Option<BoardSector>>> {
+	) -> QueryResult<RwLockWriteGuard<'_, Option<BoardSector>>> {
 		let mut option = self
 			.sectors
 			.get(sector_index)
 			.unwrap()
 			.write();
 
+		// Another access may have filled the sector while we waited for the
+		// lock. Loading it again would be wasted work at best and would
+		// discard any changes made since at worst.
+		if option.is_some() {
+			return Ok(option);
+		}
+
 		let load = BoardSector::load(
 			self.board_id,
 			sector_index as i32,
@@ -82,7 +90,7 @@ impl SectorCache {
 		&self,
 		sector_index: usize,
 		connection: &mut Connection,
-	) -> Option<MappedRwLockReadGuard<BoardSector>> {
+	) -> Option<MappedRwLockReadGuard<'_, BoardSector>> {
 		if let Some(lock) = self.sectors.get(sector_index) {
 			let option = lock.read();
 			if option.is_some() {
@@ -104,29 +112,80 @@ impl SectorCache {
 		}
 	}
 
+	/// Like [`Self::read_sector`], but sectors which don't exist yet are
+	/// returned as None rather than being created.
+	pub fn read_existing_sector(
+		&self,
+		sector_index: usize,
+		connection: &mut Connection,
+	) -> QueryResult<Option<MappedRwLockReadGuard<'_, BoardSector>>> {
+		let lock = match self.sectors.get(sector_index) {
+			Some(lock) => lock,
+			None => return Ok(None),
+		};
+
+		let option = lock.read();
+		if option.is_some() {
+			return Ok(Some(RwLockReadGuard::map(option, |o| o.as_ref().unwrap())));
+		}
+		drop(option);
+
+		let mut option = lock.write();
+		// Another access may have filled the sector while we waited for the
+		// lock.
+		if option.is_none() {
+			let load = BoardSector::load(
+				self.board_id,
+				sector_index as i32,
+				connection,
+			)?;
+
+			match load {
+				Some(sector) => option.replace(sector),
+				None => return Ok(None),
+			};
+		}
+
+		Ok(Some(RwLockReadGuard::map(
+			RwLockWriteGuard::downgrade(option),
+			|o| o.as_ref().unwrap(),
+		)))
+	}
+
 	pub fn write_sector(
 		&self,
 		sector_index: usize,
 		connection: &mut Connection,
-	) -> Option<MappedRwLockWriteGuard<BoardSector>> {
+	) -> QueryResult<Option<MappedRwLockWriteGuard<'_, BoardSector>>> {
 		if let Some(lock) = self.sectors.get(sector_index) {
 			let option = lock.write();
 			if option.is_some() {
-				Some(RwLockWriteGuard::map(option, |o| o.as_mut().unwrap()))
+				Ok(Some(RwLockWriteGuard::map(option, |o| o.as_mut().unwrap())))
 			} else {
 				drop(option);
 
-				let sector = self
-					.fill_sector(sector_index, connection)
-					.unwrap();
+				let sector = self.fill_sector(sector_index, connection)?;
 
-				Some(RwLockWriteGuard::map(sector, |o| o.as_mut().unwrap()))
+				Ok(Some(RwLockWriteGuard::map(sector, |o| o.as_mut().unwrap())))
 			}
 		} else {
-			None
+			Ok(None)
 		}
 	}
 
+	/// The index and size in bytes of each loaded sector.
+	pub fn loaded_sectors(&self) -> Vec<(usize, usize)> {
+		self.sectors
+			.iter()
+			.enumerate()
+			.filter_map(|(index, lock)| {
+				lock.read()
+					.as_ref()
+					.map(|sector| (index, sector.memory_size()))
+			})
+			.collect()
+	}
+
 	pub fn access<'l>(
 		&'l self,
 		buffer: SectorBuffer,
@@ -143,10 +202,6 @@ impl SectorCache {
 
 pub trait Len {
 	fn len(&self) -> usize;
-
-	fn is_empty(&self) -> bool {
-		self.len() == 0
-	}
 }
 
 pub struct SectorCacheAccess<'l> {
@@ -164,6 +219,7 @@ impl<'l> SectorCacheAccess<'l> {
 				SectorBuffer::Timestamps => 4,
 				SectorBuffer::Initial => 1,
 				SectorBuffer::Mask => 1,
+				SectorBuffer::Density => 4,
 			}
 	}
 }
@@ -204,7 +260,7 @@ impl<'l> Seek for SectorCacheAccess<'l> {
 			.and_then(usize::try_from)
 			.map(|new_cursor| self.cursor = new_cursor)
 			.and_then(|_| u64::try_from(self.cursor))
-			.map_err(|e| std::io::Error::new(ErrorKind::Other, e))
+			.map_err(std::io::Error::other)
 	}
 }
 
@@ -232,6 +288,7 @@ impl<'l> Read for SectorCacheAccess<'l> {
 				SectorBuffer::Timestamps => &sector.timestamps,
 				SectorBuffer::Initial => &sector.initial,
 				SectorBuffer::Mask => &sector.mask,
+				SectorBuffer::Density => &sector.density,
 			}[offset..];
 
 			let write_len = buf.read(output)?;
@@ -271,7 +328,7 @@ impl<'l> Write for SectorCacheAccess<'l> {
 
 					let mut sector = self
 						.sectors
-						.write_sector(sector_index, connection)
+						.write_sector(sector_index, connection)?
 						.unwrap();
 
 					let buf = &mut match self.buffer {
@@ -279,6 +336,7 @@ impl<'l> Write for SectorCacheAccess<'l> {
 						SectorBuffer::Timestamps => &mut sector.timestamps,
 						SectorBuffer::Initial => &mut sector.initial,
 						SectorBuffer::Mask => &mut sector.mask,
+						SectorBuffer::Density => &mut sector.density,
 					}[offset..];
 
 					let write_len: usize = input.read(buf).unwrap();
@@ -1,19 +1,25 @@
-use std::ops::Range;
-
 pub trait Shape {
 	fn sector_size(&self) -> usize;
 	fn sector_count(&self) -> usize;
 	fn total_size(&self) -> usize;
-	fn dimensions(&self) -> usize;
+	/// The total size of each dimension if the shape is uniform.
+	fn extents(&self) -> Option<Vec<usize>>;
+	/// The position of some coordinates within the shape.
+	fn position(
+		&self,
+		coordinates: &[usize],
+	) -> Option<usize>;
+	/// Whether every position of `other` has an equivalent position in this
+	/// shape when both are aligned at the origin.
+	fn encloses(
+		&self,
+		other: &Self,
+	) -> bool;
 	fn transform(
 		from: &Self,
 		to: &Self,
 		position: usize,
-	) -> usize;
-	fn to_local(
-		&self,
-		position: usize,
-	) -> Option<(usize, usize)>;
+	) -> Option<usize>;
 
 	fn contains(
 		&self,
@@ -21,20 +27,90 @@ pub trait Shape {
 	) -> bool {
 		(0..self.total_size()).contains(position)
 	}
-
-	fn sectors_within(
-		&self,
-		range: Range<usize>,
-	) -> Range<usize> {
-		let sector_size = self.sector_size();
-		let start = range.start / sector_size;
-		let end = range.end / sector_size;
-		start..end.min(self.sector_count())
-	}
 }
 
 pub type VecShape = Vec<Vec<usize>>;
 
+/// The smallest sector size worth splitting a board into. Sizes with few
+/// divisors (such as primes) would otherwise make tiny sectors.
+const MIN_SECTOR_SIZE: usize = 64;
+
+/// How the positions of a board are split into sectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectorLayout {
+	pub size: usize,
+	pub count: usize,
+}
+
+impl SectorLayout {
+	/// Split `shape` into sectors of at most `target` positions, or into the
+	/// last level of the shape if there is no target or no reasonable sector
+	/// size fits it.
+	pub fn new(
+		shape: &VecShape,
+		target: Option<usize>,
+	) -> Self {
+		target
+			.and_then(|target| Self::fitting(shape.total_size(), target))
+			.unwrap_or_else(|| Self::of(shape))
+	}
+
+	/// The sectors described by the last level of `shape`.
+	pub fn of(shape: &VecShape) -> Self {
+		Self {
+			size: shape.sector_size(),
+			count: shape.sector_count(),
+		}
+	}
+
+	/// The largest sector size no bigger than `target` which evenly divides
+	/// `total_size`, so that every sector is the same size.
+	/// None if that size is smaller than [`MIN_SECTOR_SIZE`] (or the whole
+	/// board or `target` if those are smaller still).
+	fn fitting(
+		total_size: usize,
+		target: usize,
+	) -> Option<Self> {
+		if total_size == 0 {
+			return Some(Self { size: 0, count: 0 });
+		}
+
+		let mut size = 1;
+		let mut divisor = 1;
+		while divisor * divisor <= total_size {
+			if total_size.is_multiple_of(divisor) {
+				for candidate in [divisor, total_size / divisor] {
+					if candidate <= target && candidate > size {
+						size = candidate;
+					}
+				}
+			}
+			divisor += 1;
+		}
+
+		if size < MIN_SECTOR_SIZE.min(target).min(total_size) {
+			return None;
+		}
+
+		Some(Self {
+			size,
+			count: total_size / size,
+		})
+	}
+
+	/// The sector index and offset within it of a position.
+	pub fn to_local(
+		self,
+		position: usize,
+	) -> Option<(usize, usize)> {
+		if position < self.size * self.count {
+			Some((position / self.size, position % self.size))
+		} else {
+			None
+		}
+	}
+}
+
 // TODO: StructShape (or something) which stores these values on new
 // rather than recomputing them.
 impl Shape for VecShape {
@@ -57,27 +133,160 @@ impl Shape for VecShape {
 		self.sector_count() * self.sector_size()
 	}
 
-	fn dimensions(&self) -> usize {
-		self.len()
+	fn extents(&self) -> Option<Vec<usize>> {
+		extents(self)
 	}
 
-	fn transform(
-		from: &Self,
-		to: &Self,
-		position: usize,
-	) -> usize {
-		todo!("implement shape-to-shape transforming")
+	fn position(
+		&self,
+		coordinates: &[usize],
+	) -> Option<usize> {
+		encode(self, coordinates)
 	}
 
-	fn to_local(
+	fn encloses(
 		&self,
+		other: &Self,
+	) -> bool {
+		match (extents(self), extents(other)) {
+			(Some(outer), Some(inner)) => {
+				outer.len() == inner.len()
+					&& outer.iter().zip(inner.iter()).all(|(o, i)| o >= i)
+			},
+			_ => false,
+		}
+	}
+
+	fn transform(
+		from: &Self,
+		to: &Self,
 		position: usize,
-	) -> Option<(usize, usize)> {
-		if self.contains(&position) {
-			let size = self.sector_size();
-			Some((position / size, position % size))
+	) -> Option<usize> {
+		if from.contains(&position) {
+			let coordinates = coordinates(from, position)?;
+			encode(to, &coordinates)
 		} else {
 			None
 		}
 	}
 }
+
+/// The total size of each dimension across all levels of the shape.
+/// None if the levels don't all have the same number of dimensions.
+fn extents(shape: &VecShape) -> Option<Vec<usize>> {
+	let dimensions = shape.first()?.len();
+
+	if shape.iter().all(|level| level.len() == dimensions) {
+		Some(
+			(0..dimensions)
+				.map(|d| shape.iter().map(|level| level[d]).product())
+				.collect(),
+		)
+	} else {
+		None
+	}
+}
+
+// NOTE: positions are treated as mixed-radix numbers where the last dimension
+// of the last level is the least significant digit. This matches how sectors
+// are split from positions in `to_local`.
+fn coordinates(
+	shape: &VecShape,
+	mut position: usize,
+) -> Option<Vec<usize>> {
+	let dimensions = extents(shape)?.len();
+	let mut coordinates = vec![0; dimensions];
+	let mut scales = vec![1; dimensions];
+
+	for level in shape.iter().rev() {
+		for (d, size) in level.iter().enumerate().rev() {
+			coordinates[d] += (position % size) * scales[d];
+			scales[d] *= size;
+			position /= size;
+		}
+	}
+
+	Some(coordinates)
+}
+
+fn encode(
+	shape: &VecShape,
+	coordinates: &[usize],
+) -> Option<usize> {
+	let extents = extents(shape)?;
+
+	let in_bounds = extents.len() == coordinates.len()
+		&& coordinates.iter().zip(extents.iter()).all(|(c, e)| c < e);
+
+	if !in_bounds {
+		return None;
+	}
+
+	let mut scales = extents;
+	let mut position = 0;
+
+	for level in shape {
+		for (d, size) in level.iter().enumerate() {
+			scales[d] /= size;
+			position = position * size + (coordinates[d] / scales[d]) % size;
+		}
+	}
+
+	Some(position)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fitting_picks_the_largest_divisor_within_the_target() {
+		assert_eq!(
+			SectorLayout::fitting(1000 * 1000, 4096),
+			Some(SectorLayout { size: 4000, count: 250 }),
+		);
+		assert_eq!(
+			SectorLayout::fitting(1024, 4096),
+			Some(SectorLayout { size: 1024, count: 1 }),
+		);
+	}
+
+	#[test]
+	fn fitting_rejects_tiny_sectors() {
+		// 4099 is prime so the only divisor within the target is 1
+		assert_eq!(SectorLayout::fitting(4099, 1024), None);
+		assert_eq!(SectorLayout::fitting(2 * 4099, 1024), None);
+	}
+
+	#[test]
+	fn fitting_allows_small_boards_and_targets() {
+		assert_eq!(
+			SectorLayout::fitting(7, 4096),
+			Some(SectorLayout { size: 7, count: 1 }),
+		);
+		assert_eq!(
+			SectorLayout::fitting(1000, 10),
+			Some(SectorLayout { size: 10, count: 100 }),
+		);
+	}
+
+	#[test]
+	fn new_falls_back_to_the_shape_for_prime_sizes() {
+		let shape: VecShape = vec![vec![1, 1], vec![4099, 1]];
+
+		assert_eq!(
+			SectorLayout::new(&shape, Some(1024)),
+			SectorLayout { size: 4099, count: 1 },
+		);
+	}
+
+	#[test]
+	fn new_uses_the_shape_without_a_target() {
+		let shape: VecShape = vec![vec![2, 2], vec![64, 64]];
+
+		assert_eq!(
+			SectorLayout::new(&shape, None),
+			SectorLayout { size: 4096, count: 4 },
+		);
+	}
+}
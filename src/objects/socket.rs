@@ -1,5 +1,8 @@
 use core::hash::Hash;
 use std::{
+	io::Write,
+	ops::Range,
+	str::FromStr,
 	sync::{Arc, Weak},
 	time::{Duration, SystemTime},
 };
@@ -7,10 +10,11 @@ use std::{
 use async_trait::async_trait;
 use enum_map::Enum;
 use enumset::{EnumSet, EnumSetType};
+use flate2::{write::GzEncoder, Compression};
 use futures_util::{stream::SplitStream, FutureExt, StreamExt};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, time::Instant};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use uuid::Uuid;
 use warp::ws;
@@ -18,16 +22,54 @@ use warp::ws;
 use crate::{
 	access::permissions::Permission,
 	authentication::openid::ValidationError,
+	config::CONFIG,
 	database::Pool,
 	objects::{packet, AuthedUser, Board, User},
 };
 
 #[derive(Debug, EnumSetType, Enum, Deserialize, Serialize)]
-#[enumset(serialize_as_list)]
+#[enumset(serialize_repr = "list")]
 #[serde(rename_all = "lowercase")]
 pub enum Extension {
 	Core,
 	Authentication,
+	/// Large packets are sent gzipped as binary messages.
+	Gzip,
+}
+
+impl Extension {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Core => "core",
+			Self::Authentication => "authentication",
+			Self::Gzip => "gzip",
+		}
+	}
+}
+
+impl FromStr for Extension {
+	type Err = ();
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		EnumSet::<Self>::all()
+			.into_iter()
+			.find(|extension| extension.as_str() == value)
+			.ok_or(())
+	}
+}
+
+/// Whether a user has the permissions to use every one of some extensions.
+fn permits_extensions(
+	user: &AuthedUser,
+	extensions: EnumSet<Extension>,
+) -> bool {
+	let default_user = User::default();
+	let user = Option::<&User>::from(user).unwrap_or(&default_user);
+
+	extensions
+		.iter()
+		.map(Permission::from)
+		.all(|permission| user.permissions.contains(&permission))
 }
 
 impl From<Extension> for Permission {
@@ -35,6 +77,7 @@ impl From<Extension> for Permission {
 		match extension {
 			Extension::Core => Self::SocketCore,
 			Extension::Authentication => Self::SocketAuthentication,
+			Extension::Gzip => Self::SocketGzip,
 		}
 	}
 }
@@ -42,6 +85,7 @@ impl From<Extension> for Permission {
 enum Message {
 	Close,
 	Ping,
+	Pong,
 	Packet(packet::client::Packet),
 	Invalid,
 }
@@ -56,6 +100,8 @@ impl From<ws::Message> for Message {
 			}
 		} else if message.is_ping() {
 			Self::Ping
+		} else if message.is_pong() {
+			Self::Pong
 		} else if message.is_close() {
 			Self::Close
 		} else {
@@ -83,18 +129,22 @@ enum AuthFailure {
 	Closed,
 	InvalidMessage,
 	Unauthorized,
+	#[allow(dead_code)]
 	ValidationError(ValidationError),
 }
 
 pub struct UnauthedSocket {
 	sender: mpsc::UnboundedSender<Result<ws::Message, warp::Error>>,
 	extensions: EnumSet<Extension>,
+	region: Option<Vec<Range<u64>>>,
 }
 
 impl UnauthedSocket {
 	pub async fn connect(
 		websocket: ws::WebSocket,
 		extensions: EnumSet<Extension>,
+		region: Option<Vec<Range<u64>>>,
+		since: Option<u32>,
 		board: Weak<RwLock<Option<Board>>>,
 		connection_pool: Arc<Pool>,
 	) {
@@ -107,13 +157,17 @@ impl UnauthedSocket {
 			sender_receiver
 				.forward(ws_sender)
 				.map(|result| {
-					if let Err(e) = result {
-						eprintln!("error sending websocket msg: {}", e);
+					if let Err(err) = result {
+						tracing::warn!(%err, "error sending websocket message");
 					}
 				}),
 		);
 
-		let socket = Self { sender, extensions };
+		let socket = Self {
+			sender,
+			extensions,
+			region,
+		};
 
 		let timeout = tokio::time::sleep(Duration::from_secs(5));
 
@@ -126,18 +180,39 @@ impl UnauthedSocket {
 			let socket = Arc::new(socket);
 
 			// add socket
+			let mut inserted = Ok(true);
 			if let Some(board) = board.upgrade() {
+				let mut connection = match connection_pool.get() {
+					Ok(connection) => connection,
+					Err(err) => {
+						tracing::error!(%err, "failed to get a database connection for a socket");
+						socket.close_with(CLOSE_INTERNAL_ERROR, "database unavailable");
+						return;
+					},
+				};
+
 				let mut board = board.write();
 				if let Some(ref mut board) = *board {
-					let mut connection = connection_pool.get().unwrap();
-					board
-						.insert_socket(Arc::clone(&socket), &mut connection)
-						.unwrap();
+					inserted = board.insert_socket(Arc::clone(&socket), since, &mut connection);
 				}
 			}
 
+			match inserted {
+				Ok(true) => (),
+				// The board filled up since the upgrade was accepted.
+				Ok(false) => {
+					socket.close_with(CLOSE_TRY_AGAIN_LATER, "board is full");
+					return;
+				},
+				Err(err) => {
+					tracing::error!(%err, "failed to add a socket to its board");
+					socket.close_with(CLOSE_INTERNAL_ERROR, "database error");
+					return;
+				},
+			}
+
 			socket
-				.handle_packets(&mut ws_receiver)
+				.handle_packets(&mut ws_receiver, &board, &connection_pool)
 				.await;
 
 			// remove socket
@@ -162,6 +237,7 @@ impl UnauthedSocket {
 				uuid: Uuid::new_v4(),
 				sender: self.sender,
 				extensions: self.extensions,
+				region: self.region,
 				user: RwLock::new(AuthedUser::None),
 			});
 		}
@@ -180,25 +256,12 @@ impl UnauthedSocket {
 					return user
 						.map_err(AuthFailure::ValidationError)
 						.and_then(|user| {
-							let default_user = User::default();
-							let actual_user = Option::<&User>::from(&user)
-								.unwrap_or(&default_user);
-
-							let has_permission = self
-								.extensions
-								.iter()
-								.map(Permission::from)
-								.all(|permission| {
-									actual_user
-										.permissions
-										.contains(&permission)
-								});
-
-							if has_permission {
+							if permits_extensions(&user, self.extensions) {
 								Ok(AuthedSocket {
 									uuid: Uuid::new_v4(),
 									sender: self.sender,
 									extensions: self.extensions,
+									region: self.region,
 									user: RwLock::new(user),
 								})
 							} else {
@@ -206,10 +269,10 @@ impl UnauthedSocket {
 							}
 						});
 				},
-				Message::Packet(_) => return Err(AuthFailure::InvalidMessage),
 				Message::Invalid => return Err(AuthFailure::InvalidMessage),
 				Message::Close => (),
 				Message::Ping => (),
+				Message::Pong => (),
 			}
 		}
 
@@ -222,6 +285,8 @@ pub struct AuthedSocket {
 	uuid: Uuid,
 	sender: mpsc::UnboundedSender<Result<ws::Message, warp::Error>>,
 	pub extensions: EnumSet<Extension>,
+	/// The positions this socket receives board changes for, if limited.
+	pub region: Option<Vec<Range<u64>>>,
 	pub user: RwLock<AuthedUser>,
 }
 
@@ -245,15 +310,77 @@ impl Hash for AuthedSocket {
 	}
 }
 
+/// A token bucket limiting how fast a single socket can send messages.
+struct MessageLimiter {
+	tokens: f64,
+	updated: Instant,
+}
+
+impl MessageLimiter {
+	fn new() -> Self {
+		Self {
+			tokens: f64::from(CONFIG.socket_message_burst),
+			updated: Instant::now(),
+		}
+	}
+
+	/// Take a token for a received message, returning false if there are none.
+	fn allow(&mut self) -> bool {
+		if CONFIG.socket_message_rate == 0 {
+			return true;
+		}
+
+		let now = Instant::now();
+		let elapsed = now.duration_since(self.updated).as_secs_f64();
+		self.updated = now;
+		self.tokens = (self.tokens + elapsed * f64::from(CONFIG.socket_message_rate))
+			.min(f64::from(CONFIG.socket_message_burst));
+
+		if self.tokens >= 1.0 {
+			self.tokens -= 1.0;
+			true
+		} else {
+			false
+		}
+	}
+}
+
+/// The close code for a server which is temporarily unable to accept the
+/// socket.
+const CLOSE_TRY_AGAIN_LATER: u16 = 1013;
+
+/// The close code for a server which hit an unexpected error.
+const CLOSE_INTERNAL_ERROR: u16 = 1011;
+
+/// Packets smaller than this (in bytes) aren't worth compressing.
+const GZIP_THRESHOLD: usize = 1024;
+
+/// A serialized packet as a websocket message, gzipped as binary if `gzip`
+/// is set and the packet is large enough to be worth it.
+fn encode_message(
+	text: String,
+	gzip: bool,
+) -> ws::Message {
+	if gzip && text.len() >= GZIP_THRESHOLD {
+		let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(text.as_bytes()).unwrap();
+		ws::Message::binary(encoder.finish().unwrap())
+	} else {
+		ws::Message::text(text)
+	}
+}
+
 impl AuthedSocket {
 	pub fn send(
 		&self,
 		message: &packet::server::Packet,
 	) {
-		let message = ws::Message::text(serde_json::to_string(message).unwrap());
+		let text = serde_json::to_string(message).unwrap();
+		let message = encode_message(text, self.extensions.contains(Extension::Gzip));
 
 		if self.auth_valid() {
-			self.sender.send(Ok(message));
+			// a closed receiver means the socket is already going away
+			let _ = self.sender.send(Ok(message));
 		} else {
 			self.close();
 		}
@@ -271,50 +398,184 @@ impl AuthedSocket {
 	}
 
 	pub fn close(&self) {
-		self.sender.send(Ok(ws::Message::close()));
+		let _ = self.sender.send(Ok(ws::Message::close()));
 	}
 
-	async fn handle_packets(
+	pub fn close_with(
 		&self,
+		code: u16,
+		reason: &'static str,
+	) {
+		let _ = self.sender.send(Ok(ws::Message::close_with(code, reason)));
+	}
+
+	/// Handle incoming messages until the socket closes or stops responding.
+	///
+	/// Sockets are pinged periodically (unless the ping interval is zero) and
+	/// must send something back within the pong timeout or they are
+	/// considered dead.
+	/// Sockets which send messages faster than the configured rate are closed.
+	async fn handle_packets(
+		self: &Arc<Self>,
 		receiver: &mut SplitStream<ws::WebSocket>,
+		board: &Weak<RwLock<Option<Board>>>,
+		connection_pool: &Arc<Pool>,
 	) {
-		while let Some(Ok(msg)) = receiver.receive().await {
-			match msg {
-				Message::Packet(packet::client::Packet::Authenticate { token }) => {
-					if self.extensions.contains(Extension::Authentication) {
-						let user = if let Some(token) = token {
-							crate::authentication::openid::validate_token(&token)
-								.await
-								.map(AuthedUser::from)
-						} else {
-							Ok(AuthedUser::None)
-						};
-
-						match user {
-							Ok(user) => {
-								let mut current_user = self.user.write();
-								// NOTE: AuthedUser::eq tests only the subject
-								// and not the expiry
-								if *current_user == user {
-									*current_user = user;
-								} else {
-									self.close();
-								}
-							},
-							Err(_) => {
+		let pong_timeout = Duration::from_secs(CONFIG.socket_pong_timeout);
+		let ping_interval = Duration::from_secs(CONFIG.socket_ping_interval);
+		let pings = !ping_interval.is_zero();
+		// NOTE: intervals panic with a zero period, so one is still needed
+		// even though the heartbeat is never polled without pings.
+		let ping_interval = ping_interval.max(Duration::from_secs(1));
+		let mut heartbeat = tokio::time::interval_at(
+			Instant::now() + ping_interval,
+			ping_interval,
+		);
+		let mut pong_deadline: Option<Instant> = None;
+		let mut limiter = MessageLimiter::new();
+
+		loop {
+			tokio::select! {
+				message = receiver.receive() => {
+					match message {
+						Some(Ok(message)) => {
+							if !limiter.allow() {
+								tracing::debug!(socket = %self.uuid, "closing socket for exceeding the message rate");
 								self.close();
-							},
-						}
-					} else {
-						self.close();
+								break;
+							}
+							pong_deadline = None;
+							self.handle_message(message, board, connection_pool).await;
+						},
+						_ => break,
 					}
 				},
-				Message::Invalid => {
+				_ = heartbeat.tick(), if pings => {
+					if pong_deadline.is_none() {
+						pong_deadline = Some(Instant::now() + pong_timeout);
+						let _ = self.sender.send(Ok(ws::Message::ping(Vec::new())));
+					}
+				},
+				_ = tokio::time::sleep_until(pong_deadline.unwrap_or_else(Instant::now)),
+					if pong_deadline.is_some() =>
+				{
+					tracing::debug!(socket = %self.uuid, "closing unresponsive socket");
 					self.close();
+					break;
 				},
-				Message::Close => (),
-				Message::Ping => (),
 			}
 		}
 	}
+
+	async fn handle_message(
+		self: &Arc<Self>,
+		message: Message,
+		board: &Weak<RwLock<Option<Board>>>,
+		connection_pool: &Arc<Pool>,
+	) {
+		match message {
+			Message::Packet(packet::client::Packet::Authenticate { token }) => {
+				if self.extensions.contains(Extension::Authentication) {
+					let user = if let Some(token) = token {
+						crate::authentication::openid::validate_token(&token)
+							.await
+							.map(AuthedUser::from)
+					} else {
+						Ok(AuthedUser::None)
+					};
+
+					match user {
+						Ok(user) => {
+							// NOTE: AuthedUser::eq tests only the subject
+							// and not the expiry
+							let same_user = *self.user.read() == user;
+							if same_user {
+								*self.user.write() = user;
+							} else if !permits_extensions(&user, self.extensions) {
+								self.close();
+							} else if let Some(board) = board.upgrade() {
+								// The socket now acts as a different user, so
+								// it moves to that user's connections.
+								let mut connection = match connection_pool.get() {
+									Ok(connection) => connection,
+									Err(err) => {
+										tracing::error!(%err, "failed to get a database connection for a socket");
+										self.close_with(CLOSE_INTERNAL_ERROR, "database unavailable");
+										return;
+									},
+								};
+
+								let mut board = board.write();
+								if let Some(ref mut board) = *board {
+									let moved = board.reauthenticate_socket(
+										Arc::clone(self),
+										user,
+										&mut connection,
+									);
+
+									if let Err(err) = moved {
+										tracing::error!(%err, "failed to reauthenticate a socket");
+										self.close_with(CLOSE_INTERNAL_ERROR, "database error");
+									}
+								}
+							}
+						},
+						Err(_) => {
+							self.close();
+						},
+					}
+				} else {
+					self.close();
+				}
+			},
+			Message::Invalid => {
+				self.close();
+			},
+			Message::Close => (),
+			Message::Ping => (),
+			Message::Pong => (),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Read;
+
+	use flate2::read::GzDecoder;
+
+	use super::*;
+
+	#[test]
+	fn small_packets_are_sent_as_text() {
+		let text = "{\"type\":\"ready\"}".to_owned();
+
+		let message = encode_message(text.clone(), true);
+
+		assert_eq!(message.to_str(), Ok(text.as_str()));
+	}
+
+	#[test]
+	fn large_packets_are_gzipped_when_requested() {
+		let text = format!("\"{}\"", "x".repeat(GZIP_THRESHOLD));
+
+		let message = encode_message(text.clone(), true);
+		assert!(message.is_binary());
+		assert!(message.as_bytes().len() < text.len());
+
+		let mut decoded = String::new();
+		GzDecoder::new(message.as_bytes())
+			.read_to_string(&mut decoded)
+			.unwrap();
+		assert_eq!(decoded, text);
+	}
+
+	#[test]
+	fn large_packets_are_text_without_gzip() {
+		let text = format!("\"{}\"", "x".repeat(GZIP_THRESHOLD));
+
+		let message = encode_message(text.clone(), false);
+
+		assert_eq!(message.to_str(), Ok(text.as_str()));
+	}
 }
@@ -22,6 +22,7 @@ impl User {
 		permissions.insert(Permission::BoardsPixelsPost);
 		permissions.insert(Permission::BoardsGet);
 		permissions.insert(Permission::SocketCore);
+		permissions.insert(Permission::SocketGzip);
 
 		Self {
 			id: Some(id),
@@ -52,9 +53,11 @@ impl Default for User {
 		permissions.insert(Permission::BoardsDataGet);
 		permissions.insert(Permission::BoardsDataPatch);
 		permissions.insert(Permission::BoardsUsers);
+		permissions.insert(Permission::BoardsUsersPixelsList);
 		permissions.insert(Permission::BoardsPixelsList);
 		permissions.insert(Permission::BoardsPixelsGet);
 		permissions.insert(Permission::SocketCore);
+		permissions.insert(Permission::SocketGzip);
 
 		Self {
 			id: None,
@@ -144,3 +147,22 @@ impl Hash for AuthedUser {
 		Option::<&User>::from(self).hash(state);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn anonymous_users_cannot_purge_boards() {
+		let user = User::from(AuthedUser::None);
+
+		assert!(!user.permissions.contains(&Permission::BoardsPurge));
+	}
+
+	#[test]
+	fn anonymous_users_cannot_clear_pixels() {
+		let user = User::from(AuthedUser::None);
+
+		assert!(!user.permissions.contains(&Permission::BoardsPixelsDelete));
+	}
+}
@@ -0,0 +1,54 @@
+use super::*;
+use crate::{filters::resource::board::PassableBoard, BoardDataMap};
+
+pub fn reload_config() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("admin")
+		.and(warp::path("config"))
+		.and(warp::path("reload"))
+		.and(warp::path::end())
+		.and(warp::post())
+		.and(authorization::bearer().and_then(with_permission(Permission::ConfigReload)))
+		.map(|_user| {
+			match crate::config::reload() {
+				Ok(()) => StatusCode::NO_CONTENT.into_response(),
+				Err(err) => {
+					tracing::error!(%err, "failed to reload config");
+					reply::with_status(err.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+						.into_response()
+				},
+			}
+		})
+}
+
+#[derive(Serialize)]
+struct LoadedSector {
+	index: usize,
+	/// Approximately how much memory the sector uses.
+	bytes: usize,
+}
+
+/// List the sectors of a board which are loaded in memory.
+/// Writes are saved as they happen so no sector has unsaved changes.
+pub fn sectors(boards: BoardDataMap) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("sectors"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsSectorsGet)))
+		.map(|board: PassableBoard, _user| {
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+
+			let sectors = board
+				.loaded_sectors()
+				.into_iter()
+				.map(|(index, bytes)| LoadedSector { index, bytes })
+				.collect::<Vec<_>>();
+
+			json(&sectors).into_response()
+		})
+}
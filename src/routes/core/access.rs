@@ -6,10 +6,12 @@ pub fn get() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone
 		.and(warp::get())
 		.and(authorization::bearer().map(User::from))
 		.map(|user: User| {
-			json(
-				&Option::<User>::from(user)
-					.unwrap_or_default()
-					.permissions,
-			)
+			// sorted so that the response is stable between requests
+			let mut permissions = user.permissions
+				.into_iter()
+				.collect::<Vec<_>>();
+			permissions.sort();
+
+			json(&permissions)
 		})
 }
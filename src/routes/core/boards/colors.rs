@@ -0,0 +1,114 @@
+use super::*;
+
+#[derive(serde::Serialize)]
+struct ColorUsage<'l> {
+	#[serde(flatten)]
+	color: &'l Color,
+	/// The number of pixels currently this color.
+	usage: usize,
+}
+
+pub fn get(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("colors"))
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
+		.and(database::connection(database_pool))
+		.map(|board: PassableBoard, index: u32, user, mut connection| {
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			let color = match board.color(index) {
+				Some(color) => color,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+
+			// palette indices are validated to fit in a byte
+			match board.color_usage(index as u8, &mut connection) {
+				Ok(usage) => json(&ColorUsage { color, usage }).into_response(),
+				Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			}
+		})
+}
+
+pub fn histogram(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("colors"))
+		.and(warp::path("histogram"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
+		.and(database::connection(database_pool))
+		.map(|board: PassableBoard, user, mut connection| {
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			match board.color_histogram(&mut connection) {
+				Ok(histogram) => json(&histogram).into_response(),
+				Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			}
+		})
+}
+
+#[derive(serde::Deserialize)]
+pub struct PatchOptions {
+	/// A color which pixels of removed colors are changed to.
+	pub remap_to: Option<u32>,
+}
+
+pub fn patch(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("colors"))
+		.and(warp::path::end())
+		.and(serde_qs::warp::query(Default::default()))
+		.and(patch::merge(CONFIG.max_patch_body_size))
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPatch)))
+		.and(database::connection(database_pool))
+		.map(|
+			board: PassableBoard,
+			options: PatchOptions,
+			patch: PalettePatch,
+			_user,
+			mut connection,
+		| {
+			let mut board = board.write();
+			let board = match board.as_mut() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+
+			match board.patch_palette(patch, options.remap_to, &mut connection) {
+				Ok(()) => {
+					webhook::notify(webhook::EventKind::BoardPatched, board);
+					json(&board.info).into_response()
+				},
+				Err(err) => err.into_response(),
+			}
+		})
+}
@@ -1,5 +1,39 @@
 use super::*;
-use crate::filters::body::patch::BinaryPatch;
+use crate::filters::{
+	body::patch::BinaryPatch,
+	header::conditional::{self, Conditions},
+};
+
+/// Respond with the requested range of a buffer, or with 304 Not Modified if
+/// the client's copy is current according to the conditional headers.
+///
+/// Validators change whenever any of the board data does. Since
+/// modification times have a resolution of one second, changes made within
+/// the same second as a request may not invalidate it for clients which only
+/// send If-Modified-Since.
+fn respond_conditionally(
+	board: &Board,
+	buffer: SectorBuffer,
+	range: Range,
+	conditions: Conditions,
+	connection: &mut crate::database::Connection,
+) -> reply::Response {
+	let (etag, last_modified) = board.data_validators();
+
+	let response = if conditions.unmodified(&etag, last_modified) {
+		StatusCode::NOT_MODIFIED.into_response()
+	} else {
+		range.respond_with(&mut board.read(buffer, connection))
+	};
+
+	let response = reply::with_header(response, header::ETAG, etag).into_response();
+	reply::with_header(
+		response,
+		header::LAST_MODIFIED,
+		httpdate::fmt_http_date(last_modified),
+	)
+	.into_response()
+}
 
 pub fn get_colors(
 	boards: BoardDataMap,
@@ -17,17 +51,109 @@ pub fn get_colors(
 				.or(range::default())
 				.unify(),
 		)
+		.and(conditional::conditions())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
 		.and(database::connection(database_pool))
-		.map(|board: PassableBoard, range: Range, _user, mut connection| {
+		.map(|board: PassableBoard, range: Range, conditions: Conditions, user, mut connection| {
 			// TODO: content disposition
 			let board = board.read();
-			let mut colors_data = board
-				.as_ref()
-				.unwrap()
-				.read(SectorBuffer::Colors, &mut connection);
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			respond_conditionally(board, SectorBuffer::Colors, range, conditions, &mut connection)
+		})
+}
+
+fn accepts_png(accept: &str) -> bool {
+	accept.split(',')
+		.map(|value| value.split(';').next().unwrap_or("").trim())
+		.any(|media_type| matches!(media_type, "image/png" | "image/*" | "*/*"))
+}
+
+pub fn get_colors_png(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("data"))
+		.and(warp::path("colors.png"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(warp::header::optional::<String>(header::ACCEPT.as_str()))
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
+		.and(database::connection(database_pool))
+		.map(|board: PassableBoard, accept: Option<String>, user, mut connection| {
+			if !accept.as_deref().map(accepts_png).unwrap_or(true) {
+				return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+			}
+
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			match board.colors_png(&mut connection) {
+				Ok(Some(png)) => {
+					Response::builder()
+						.header(header::CONTENT_TYPE, "image/png")
+						.body(png.into())
+						.unwrap()
+				},
+				// The shape can't be represented as an image
+				Ok(None) => StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
+				Err(err) => {
+					tracing::error!(board = board.id, %err, "failed to render board image");
+					StatusCode::INTERNAL_SERVER_ERROR.into_response()
+				},
+			}
+		})
+}
+
+pub fn get_thumbnail(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("thumbnail.png"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
+		.and(database::connection(database_pool))
+		.map(|board: PassableBoard, user, mut connection| {
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
 
-			range.respond_with(&mut colors_data)
+			match board.thumbnail_png(&mut connection) {
+				Ok(Some(png)) => {
+					Response::builder()
+						.header(header::CONTENT_TYPE, "image/png")
+						.body(png.into())
+						.unwrap()
+				},
+				// The shape can't be represented as an image
+				Ok(None) => StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
+				Err(err) => {
+					tracing::error!(board = board.id, %err, "failed to render board image");
+					StatusCode::INTERNAL_SERVER_ERROR.into_response()
+				},
+			}
 		})
 }
 
@@ -47,17 +173,55 @@ pub fn get_timestamps(
 				.or(range::default())
 				.unify(),
 		)
+		.and(conditional::conditions())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
+		.and(database::connection(database_pool))
+		.map(|board: PassableBoard, range: Range, conditions: Conditions, user, mut connection| {
+			// TODO: content disposition
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			respond_conditionally(board, SectorBuffer::Timestamps, range, conditions, &mut connection)
+		})
+}
+
+pub fn get_density(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("data"))
+		.and(warp::path("density"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(
+			warp::any()
+				.and(range::range())
+				.or(range::default())
+				.unify(),
+		)
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
 		.and(database::connection(database_pool))
-		.map(|board: PassableBoard, range: Range, _user, mut connection| {
-		// TODO: content disposition
+		.map(|board: PassableBoard, range: Range, user, mut connection| {
+			// TODO: content disposition
 			let board = board.read();
-			let mut timestamp_data = board
-				.as_ref()
-				.unwrap()
-				.read(SectorBuffer::Timestamps, &mut connection);
-				
-			range.respond_with(&mut timestamp_data)
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+			let mut density_data = board.read(SectorBuffer::Density, &mut connection);
+
+			range.respond_with(&mut density_data)
 		})
 }
 
@@ -79,13 +243,17 @@ pub fn get_mask(
 		)
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
 		.and(database::connection(database_pool))
-		.map(|board: PassableBoard, range: Range, _user, mut connection| {
+		.map(|board: PassableBoard, range: Range, user, mut connection| {
 			// TODO: content disposition
 			let board = board.read();
-			let mut mask_data = board
-				.as_ref()
-				.unwrap()
-				.read(SectorBuffer::Mask, &mut connection);
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+			let mut mask_data = board.read(SectorBuffer::Mask, &mut connection);
 
 			range.respond_with(&mut mask_data)
 		})
@@ -109,13 +277,17 @@ pub fn get_initial(
 		)
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataGet)))
 		.and(database::connection(database_pool))
-		.map(|board: PassableBoard, range: Range, _user, mut connection| {
+		.map(|board: PassableBoard, range: Range, user, mut connection| {
 			// TODO: content disposition
 			let board = board.read();
-			let mut initial_data = board
-				.as_ref()
-				.unwrap()
-				.read(SectorBuffer::Initial, &mut connection);
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+			let mut initial_data = board.read(SectorBuffer::Initial, &mut connection);
 
 			range.respond_with(&mut initial_data)
 		})
@@ -132,16 +304,17 @@ pub fn patch_initial(
 		.and(warp::path::end())
 		.and(warp::patch())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataPatch)))
-		.and(patch::bytes())
+		.and(patch::bytes(CONFIG.max_data_patch_body_size))
 		.and(database::connection(database_pool))
 		.map(
 			|board: PassableBoard, _user, patch: BinaryPatch, mut connection| {
 				// TODO: content disposition
 				let board = board.write();
-				let patch_result = board
-					.as_ref()
-					.unwrap()
-					.try_patch_initial(&patch, &mut connection);
+				let board = match board.as_ref() {
+					Some(board) => board,
+					None => return StatusCode::NOT_FOUND.into_response(),
+				};
+				let patch_result = board.try_patch_initial(&patch, &mut connection);
 
 				match patch_result {
 					Ok(_) => StatusCode::NO_CONTENT.into_response(),
@@ -162,16 +335,17 @@ pub fn patch_mask(
 		.and(warp::path::end())
 		.and(warp::patch())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDataPatch)))
-		.and(patch::bytes())
+		.and(patch::bytes(CONFIG.max_data_patch_body_size))
 		.and(database::connection(database_pool))
 		.map(
 			|board: PassableBoard, _user, patch: BinaryPatch, mut connection| {
 				// TODO: content disposition
 				let board = board.write();
-				let patch_result = board
-					.as_ref()
-					.unwrap()
-					.try_patch_mask(&patch, &mut connection);
+				let board = match board.as_ref() {
+					Some(board) => board,
+					None => return StatusCode::NOT_FOUND.into_response(),
+				};
+				let patch_result = board.try_patch_mask(&patch, &mut connection);
 
 				match patch_result {
 					Ok(_) => StatusCode::NO_CONTENT.into_response(),
@@ -7,11 +7,14 @@ use warp::path::Tail;
 
 use super::*;
 use crate::{
+	config::CONFIG,
 	filters::resource::board::{PassableBoard, PendingDelete},
 	objects::socket::Extension,
+	webhook,
 	BoardDataMap,
 };
 
+pub mod colors;
 pub mod data;
 pub mod pixels;
 pub mod users;
@@ -22,22 +25,25 @@ pub fn list(boards: BoardDataMap) -> impl Filter<Extract = (impl Reply,), Error
 		.and(warp::get())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsList)))
 		.and(warp::query())
-		.map(move |_user, pagination: PaginationOptions<usize>| {
+		.map(move |user, pagination: PaginationOptions<usize>| {
+			let limit = pagination.limit();
 			let page = pagination.page.unwrap_or(0);
-			let limit = pagination
-				.limit
-				.unwrap_or(10)
-				.clamp(1, 100);
 
 			let boards = Arc::clone(&boards);
 			let boards = boards.read();
-			let boards = boards
+			let mut boards = boards
 				.iter()
 				.map(|(id, board)| (id, board.read()))
 				.collect::<Vec<_>>();
+			// HashMap order is arbitrary, sort so that pages are consistent
+			boards.sort_by_key(|(id, _board)| **id);
 			let board_infos = boards
 				.iter()
-				.map(|(_id, board)| Reference::from(board.as_ref().unwrap()))
+				// boards pending deletion are None and can be skipped
+				.filter_map(|(_id, board)| board.as_ref())
+				// unlisted and private boards are only listed for privileged users
+				.filter(|board| board.listed_for(&user))
+				.map(Reference::from)
 				.collect::<Vec<_>>();
 			let mut chunks = board_infos.chunks(limit);
 
@@ -67,22 +73,42 @@ pub fn list(boards: BoardDataMap) -> impl Filter<Extract = (impl Reply,), Error
 		})
 }
 
-pub fn default() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+pub fn default(boards: BoardDataMap) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
 	warp::path("boards")
 		.and(warp::path("default"))
 		.and(warp::path::tail())
-		.map(|path_tail: Tail| {
-			// TODO: determine which board to use as default.
-			let id = 1;
+		.map(move |path_tail: Tail| {
+			let boards = boards.read();
+			let is_live = |id: &usize| {
+				boards
+					.get(id)
+					.map(|board| board.read().is_some())
+					.unwrap_or(false)
+			};
+
+			// The configured board is preferred, but if it's missing the
+			// lowest live board is used rather than redirecting to nothing.
+			let id = CONFIG.default_board
+				.filter(is_live)
+				.or_else(|| boards.keys().copied().filter(is_live).min());
+
+			let id = match id {
+				Some(id) => id,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+
+			let location = if path_tail.as_str().is_empty() {
+				format!("/boards/{}", id)
+			} else {
+				format!("/boards/{}/{}", id, path_tail.as_str())
+			};
 
 			Response::builder()
 				.status(StatusCode::SEE_OTHER)
-				.header(
-					header::LOCATION,
-					format!("/boards/{}/{}", id, path_tail.as_str()),
-				)
+				.header(header::LOCATION, location)
 				.body("")
 				.unwrap()
+				.into_response()
 		})
 }
 
@@ -95,16 +121,25 @@ pub fn get(
 		.and(warp::path::end())
 		.and(warp::get())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsGet)))
+		.and(warp::query())
 		.and(database::connection(database_pool))
-		.map(|board: PassableBoard, user, mut connection| {
+		.map(|board: PassableBoard, user, timestamps: TimestampOptions, mut connection| {
 			let board = board.read();
-			let board = board.as_ref().unwrap();
-			let mut response = json(&board.info).into_response();
+			// the board may have been deleted since the path was resolved
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+			let mut response = json(&board.info.dated(timestamps)).into_response();
 
 			if let AuthedUser::Authed { user, .. } = user {
-				let cooldown_info = board
-					.user_cooldown_info(&user, &mut connection)
-					.unwrap();
+				let cooldown_info = match board.user_cooldown_info(&user, &mut connection) {
+					Ok(cooldown_info) => cooldown_info,
+					Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+				};
 
 				for (key, value) in cooldown_info.into_headers() {
 					response = reply::with_header(response, key, value).into_response();
@@ -126,24 +161,29 @@ pub fn post(
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPost)))
 		.and(database::connection(database_pool))
 		.map(move |data: BoardInfoPost, _user, mut connection| {
-			let board = Board::create(data, &mut connection).unwrap();
-			let id = board.id as usize;
+			if let Err(duplicates) = data.check_color_indices() {
+				return duplicates.into_response();
+			}
 
-			let boards = Arc::clone(&boards);
-			let mut boards = boards.write();
-			boards.insert(id, Arc::new(RwLock::new(Some(board))));
+			let board = match Board::create(data, &mut connection) {
+				Ok(board) => board,
+				Err(err) => return err.into_response(),
+			};
+			let id = board.id as usize;
 
-			let board = boards.get(&id).unwrap().read();
-			let board = board.as_ref().unwrap();
+			webhook::notify(webhook::EventKind::BoardCreated, &board);
 
-			let mut response = json(&Reference::from(board)).into_response();
+			let mut response = json(&Reference::from(&board)).into_response();
 			response = reply::with_status(response, StatusCode::CREATED).into_response();
 			response = reply::with_header(
 				response,
 				header::LOCATION,
-				http::Uri::from(board).to_string(),
+				http::Uri::from(&board).to_string(),
 			)
 			.into_response();
+
+			boards.write().insert(id, Arc::new(RwLock::new(Some(board))));
+
 			response
 		})
 }
@@ -155,20 +195,37 @@ pub fn patch(
 	warp::path("boards")
 		.and(board::path::read(&boards))
 		.and(warp::path::end())
-		.and(warp::patch())
-		// TODO: require application/merge-patch+json type?
-		.and(warp::body::json())
+		.and(patch::merge(CONFIG.max_patch_body_size))
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPatch)))
 		.and(database::connection(database_pool))
 		.map(|board: PassableBoard, patch: BoardInfoPatch, _user, mut connection| {
+			if let Err(duplicates) = patch.check_color_indices() {
+				return duplicates.into_response();
+			}
+
 			let mut board = board.write();
-			let board = board.as_mut().unwrap();
+			let board = match board.as_mut() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+
+			let shape_change = match board.update_info(patch, &mut connection) {
+				Ok(shape_change) => shape_change,
+				Err(err) => return err.into_response(),
+			};
 
-			board.update_info(patch, &mut connection).unwrap();
+			webhook::notify(webhook::EventKind::BoardPatched, board);
 
 			let mut response = json(&Reference::from(&*board)).into_response();
-			response = reply::with_status(response, StatusCode::CREATED).into_response();
 			response = reply::with_header(response, header::LOCATION, http::Uri::from(&*board).to_string()).into_response();
+			if shape_change == Some(ShapeChange::Discarded) {
+				response = reply::with_header(
+					response,
+					header::WARNING,
+					"299 - \"board data was discarded by the shape change\"",
+				)
+				.into_response();
+			}
 			response
 		})
 }
@@ -181,23 +238,213 @@ pub fn delete(
 		.and(board::path::prepare_delete(&boards))
 		.and(warp::path::end())
 		.and(warp::delete())
+		.and(warp::query())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsDelete)))
 		.and(database::connection(database_pool))
 		.map(
-			move |deletion: Fragile<PendingDelete>, _user: AuthedUser, mut connection| {
+			move |
+				deletion: Fragile<PendingDelete>,
+				options: DeleteOptions,
+				user: AuthedUser,
+				mut connection,
+			| {
+				// Purging is irreversible so it needs its own permission.
+				let can_purge = Option::<&User>::from(&user)
+					.unwrap_or_default()
+					.permissions
+					.contains(&Permission::BoardsPurge);
+				if options.purge && !can_purge {
+					return StatusCode::FORBIDDEN.into_response();
+				}
+
 				let mut deletion = deletion.into_inner();
 				let board = deletion.perform();
 				let mut board = board.write();
-				let board = board.take().unwrap();
-				board.delete(&mut connection).unwrap();
+				let board = match board.take() {
+					Some(board) => board,
+					// another request deleted the board first
+					None => return StatusCode::NOT_FOUND.into_response(),
+				};
+				// The board is consumed by deleting it, so describe it now
+				// but only notify once that has succeeded.
+				let notification = webhook::Notification::new(
+					webhook::EventKind::BoardDeleted,
+					&board,
+				);
+				let id = board.id;
+				let result = if options.purge {
+					// Keep the data if it couldn't be backed up.
+					if let Err(err) = board.backup(&mut connection) {
+						tracing::error!(board = id, %err, "failed to back up board");
+						if let Err(err) = board.archive(&mut connection) {
+							tracing::error!(board = id, %err, "failed to archive board");
+						}
+						return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+					}
+					board.delete(&mut connection)
+				} else {
+					board.archive(&mut connection)
+				};
+				if let Err(err) = result {
+					tracing::error!(board = id, %err, "failed to delete board");
+					return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+				}
+				if let Some(notification) = notification {
+					notification.send();
+				}
 				StatusCode::NO_CONTENT.into_response()
 			},
 		)
 }
 
+#[derive(serde::Deserialize)]
+pub struct DeleteOptions {
+	/// Remove the board and its data permanently rather than archiving it.
+	#[serde(default)]
+	pub purge: bool,
+}
+
+pub fn restore(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(warp::path("archived"))
+		.and(warp::path::param())
+		.and(warp::path("restore"))
+		.and(warp::path::end())
+		.and(warp::post())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsRestore)))
+		.and(database::connection(database_pool))
+		.map(move |id: usize, _user, mut connection| {
+			let board_id = match i32::try_from(id) {
+				Ok(board_id) => board_id,
+				Err(_) => return StatusCode::NOT_FOUND.into_response(),
+			};
+
+			let board = match crate::database::queries::restore_board(board_id, &mut connection) {
+				Ok(Some(board)) => board,
+				Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+				Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			};
+
+			let mut response = json(&Reference::from(&board)).into_response();
+			response = reply::with_header(
+				response,
+				header::LOCATION,
+				http::Uri::from(&board).to_string(),
+			)
+			.into_response();
+
+			boards.write().insert(id, Arc::new(RwLock::new(Some(board))));
+
+			response
+		})
+}
+
+#[derive(serde::Serialize)]
+struct CooldownSchedule {
+	pixels_available: usize,
+	recent_placements: usize,
+	/// The unix time at which each further pixel becomes available.
+	schedule: Vec<u64>,
+}
+
+pub fn cooldown(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("cooldown"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPixelsPost)))
+		.and(database::connection(database_pool))
+		.map(|board: PassableBoard, user, mut connection| {
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			// Anonymous users share a single identity (and cooldown).
+			let user = User::from(user);
+
+			let cooldown_info = match board.user_cooldown_info(&user, &mut connection) {
+				Ok(cooldown_info) => cooldown_info,
+				Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			};
+
+			let schedule = cooldown_info
+				.schedule()
+				.iter()
+				.map(|time| {
+					time.duration_since(std::time::UNIX_EPOCH)
+						.unwrap()
+						.as_secs()
+				})
+				.collect();
+
+			json(&CooldownSchedule {
+				pixels_available: cooldown_info.pixels_available,
+				recent_placements: cooldown_info.recent_placements,
+				schedule,
+			})
+			.into_response()
+		})
+}
+
 #[derive(serde::Deserialize)]
 pub struct SocketOptions {
-	pub extensions: Option<enumset::EnumSet<Extension>>,
+	pub extensions: Option<Vec<String>>,
+	/// Only send board changes within these sectors.
+	pub sectors: Option<Vec<usize>>,
+	/// Replay board changes made at or after this time before streaming new
+	/// ones. Changes from the same second as this may be sent again.
+	pub since: Option<u32>,
+}
+
+/// Why the requested socket extensions were rejected.
+#[derive(serde::Serialize)]
+struct InvalidExtensions {
+	valid: Vec<&'static str>,
+	unrecognized: Vec<String>,
+}
+
+impl InvalidExtensions {
+	fn new(unrecognized: Vec<String>) -> Self {
+		Self {
+			valid: enumset::EnumSet::<Extension>::all()
+				.into_iter()
+				.map(|extension| extension.as_str())
+				.collect(),
+			unrecognized,
+		}
+	}
+}
+
+impl Reply for InvalidExtensions {
+	fn into_response(self) -> reply::Response {
+		reply::with_status(json(&self), StatusCode::UNPROCESSABLE_ENTITY).into_response()
+	}
+}
+
+/// Parse extension names, returning the unrecognized ones on failure.
+fn parse_extensions(names: Vec<String>) -> Result<enumset::EnumSet<Extension>, Vec<String>> {
+	let (extensions, unrecognized): (Vec<_>, Vec<_>) = names
+		.into_iter()
+		.map(|name| name.parse::<Extension>().map_err(|()| name))
+		.partition(Result::is_ok);
+
+	if unrecognized.is_empty() {
+		Ok(extensions.into_iter().map(Result::unwrap).collect())
+	} else {
+		Err(unrecognized.into_iter().map(Result::unwrap_err).collect())
+	}
 }
 
 pub fn socket(
@@ -214,29 +461,63 @@ pub fn socket(
 			move |board: PassableBoard, options: SocketOptions, ws: warp::ws::Ws| {
 				let database_pool = Arc::clone(&database_pool);
 
-				if let Some(extensions) = options.extensions {
-					if !extensions.is_empty() {
-						ws.on_upgrade(move |websocket| {
-							UnauthedSocket::connect(
-								websocket,
-								extensions,
-								Arc::downgrade(&*board),
-								database_pool,
-							)
-						})
-						.into_response()
-					} else {
-						StatusCode::UNPROCESSABLE_ENTITY.into_response()
-					}
-				} else {
-					StatusCode::UNPROCESSABLE_ENTITY.into_response()
+				let region = match options.sectors {
+					Some(sectors) => {
+						let board = board.read();
+						let board = match board.as_ref() {
+							Some(board) => board,
+							None => return StatusCode::NOT_FOUND.into_response(),
+						};
+
+						match board.sector_region(&sectors) {
+							Some(region) => Some(region),
+							None => return StatusCode::UNPROCESSABLE_ENTITY.into_response(),
+						}
+					},
+					None => None,
+				};
+
+				let full = match board.read().as_ref() {
+					Some(board) => board.sockets_full(),
+					None => return StatusCode::NOT_FOUND.into_response(),
+				};
+				if full {
+					return reply::with_status(
+						"board has too many sockets",
+						StatusCode::SERVICE_UNAVAILABLE,
+					)
+					.into_response();
 				}
+
+				let extensions = match options.extensions.map(parse_extensions) {
+					Some(Ok(extensions)) if !extensions.is_empty() => extensions,
+					Some(Err(unrecognized)) => {
+						return InvalidExtensions::new(unrecognized).into_response();
+					},
+					_ => return InvalidExtensions::new(vec![]).into_response(),
+				};
+
+				ws.on_upgrade(move |websocket| {
+					UnauthedSocket::connect(
+						websocket,
+						extensions,
+						region,
+						options.since,
+						Arc::downgrade(&*board),
+						database_pool,
+					)
+				})
+				.into_response()
 			},
 		)
 		.recover(|rejection: Rejection| {
 			async {
 				if let Some(err) = rejection.find::<serde_qs::Error>() {
-					Ok(StatusCode::UNPROCESSABLE_ENTITY.into_response())
+					Ok(reply::with_status(
+						err.to_string(),
+						StatusCode::UNPROCESSABLE_ENTITY,
+					)
+					.into_response())
 				} else {
 					Err(rejection)
 				}
@@ -1,4 +1,95 @@
+use std::{
+	collections::HashMap,
+	net::SocketAddr,
+	time::{Duration, Instant},
+};
+
+use futures_util::future;
+use http::{HeaderName, HeaderValue};
+
 use super::*;
+use crate::{
+	config::{self, CONFIG},
+	filters::rate_limit::{self, RateLimiter},
+};
+
+lazy_static! {
+	static ref PLACEMENT_LIMITER: RateLimiter = RateLimiter::new(|| {
+		let tunables = config::tunables();
+		(
+			tunables.placement_rate_limit,
+			Duration::from_secs(tunables.placement_rate_window),
+		)
+	});
+	static ref PLACEMENT_RESULTS: PlacementResults = PlacementResults::default();
+}
+
+/// The longest idempotency key accepted.
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct IdempotencyKey {
+	board: i32,
+	user: Option<String>,
+	key: String,
+}
+
+#[derive(Clone)]
+struct PlacementResult {
+	placed_at: Instant,
+	placement: crate::database::model::Placement,
+	headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl PlacementResult {
+	fn into_response(self) -> reply::Response {
+		let mut response = warp::reply::with_status(
+			json(&self.placement).into_response(),
+			StatusCode::CREATED,
+		)
+		.into_response();
+
+		for (key, value) in self.headers {
+			response = warp::reply::with_header(response, key, value).into_response();
+		}
+
+		response
+	}
+}
+
+/// Successful placements by idempotency key, so that retried requests can be
+/// answered without placing again.
+/// Failed placements aren't stored since retrying them is harmless.
+#[derive(Default)]
+struct PlacementResults(parking_lot::Mutex<HashMap<IdempotencyKey, PlacementResult>>);
+
+impl PlacementResults {
+	fn window() -> Duration {
+		Duration::from_secs(CONFIG.idempotency_key_ttl)
+	}
+
+	fn get(
+		&self,
+		key: &IdempotencyKey,
+	) -> Option<reply::Response> {
+		let results = self.0.lock();
+		results
+			.get(key)
+			.filter(|result| result.placed_at.elapsed() < Self::window())
+			.cloned()
+			.map(PlacementResult::into_response)
+	}
+
+	fn insert(
+		&self,
+		key: IdempotencyKey,
+		result: PlacementResult,
+	) {
+		let mut results = self.0.lock();
+		results.retain(|_, result| result.placed_at.elapsed() < Self::window());
+		results.insert(key, result);
+	}
+}
 
 pub fn list(
 	boards: BoardDataMap,
@@ -11,54 +102,88 @@ pub fn list(
 		.and(warp::get())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPixelsList)))
 		.and(warp::query())
+		.and(warp::query())
+		.and(warp::query())
 		.and(database::connection(Arc::clone(&database_pool)))
-		.map(|board: PassableBoard, _user, options: PaginationOptions<PageToken>, mut connection| {
-			let page = options.page.unwrap_or_default();
-			let limit = options
-				.limit
-				.unwrap_or(10)
-				.clamp(1, 100);
-
+		.map(|
+			board: PassableBoard,
+			user,
+			options: PaginationOptions<PageToken>,
+			filter: PlacementFilter,
+			timestamps: TimestampOptions,
+			mut connection,
+		| {
 			let board = board.read();
-			let board = board.as_ref().unwrap();
-			let previous_placements = board
-				.list_placements(page.timestamp, page.id, limit, true, &mut connection)
-				.unwrap();
-			let placements = board
-			// Limit is +1 to get the start of the next page as the last element.
-			// This is required for paging.
-			.list_placements(page.timestamp, page.id, limit + 1, false, &mut connection)
-			.unwrap();
-
-			fn page_uri(
-				board_id: i32,
-				timestamp: u32,
-				placement_id: i64,
-				limit: usize,
-			) -> String {
-				format!(
-					"/boards/{}/pixels?page={}_{}&limit={}",
-					board_id, timestamp, placement_id, limit
-				)
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
 			}
 
-			json(&Page {
-				previous: previous_placements
-					.get(0)
-					.map(|placement| {
-						page_uri(board.id, placement.timestamp as u32, placement.id, limit)
-					}),
-				items: &placements[..placements.len().clamp(0, limit)],
-				next: (placements.len() > limit)
-					.then(|| placements.iter().last().unwrap())
-					.map(|placement| {
-						page_uri(board.id, placement.timestamp as u32, placement.id, limit)
-					}),
-			})
-			.into_response()
+			let base_uri = format!("/boards/{}/pixels", board.id);
+			placement_page(board, options, &filter, timestamps, &base_uri, &mut connection)
 		})
 }
 
+/// Respond with a page of placements matching `filter`.
+/// Page links are relative to `base_uri` and carry the filter.
+pub(super) fn placement_page(
+	board: &Board,
+	options: PaginationOptions<PageToken>,
+	filter: &PlacementFilter,
+	timestamps: TimestampOptions,
+	base_uri: &str,
+	connection: &mut crate::database::Connection,
+) -> reply::Response {
+	let limit = options.limit();
+	let page = options.page.unwrap_or_default();
+
+	let previous_placements = match board
+		.list_placements(page.timestamp, page.id, limit, true, filter, connection)
+	{
+		Ok(placements) => placements,
+		Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+	};
+	// Limit is +1 to get the start of the next page as the last element.
+	// This is required for paging.
+	let placements = match board
+		.list_placements(page.timestamp, page.id, limit + 1, false, filter, connection)
+	{
+		Ok(placements) => placements,
+		Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+	};
+
+	let page_uri = |placement: &crate::database::model::Placement| {
+		format!(
+			"{}?page={}_{}&limit={}{}{}",
+			base_uri,
+			placement.timestamp as u32,
+			placement.id,
+			limit,
+			filter.to_query(),
+			timestamps.to_query(),
+		)
+	};
+
+	let items = placements[..placements.len().clamp(0, limit)]
+		.iter()
+		.map(|placement| board.dated(placement, timestamps))
+		.collect::<Vec<_>>();
+
+	json(&Page {
+		// The furthest placement back is the start of the previous page.
+		// If there's nothing before this page, there is no previous page.
+		previous: previous_placements.last().map(page_uri),
+		items: &items,
+		next: (placements.len() > limit)
+			.then(|| placements.iter().last().unwrap())
+			.map(page_uri),
+	})
+	.into_response()
+}
+
 pub fn get(
 	boards: BoardDataMap,
 	database_pool: Arc<Pool>,
@@ -70,20 +195,183 @@ pub fn get(
 		.and(warp::path::end())
 		.and(warp::get())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPixelsGet)))
+		.and(warp::query())
 		.and(database::connection(Arc::clone(&database_pool)))
-		.map(|board: PassableBoard, position, _user, mut connection| {
+		.map(|board: PassableBoard, position, user, timestamps: TimestampOptions, mut connection| {
 			let board = board.read();
-			let board = board.as_ref().unwrap();
-			let placement = board
-				.lookup(position, &mut connection)
-				.unwrap();
-
-			placement
-				.map(|placement| json(&placement).into_response())
-				.unwrap_or_else(|| StatusCode::NOT_FOUND.into_response())
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			match board.lookup(position, &mut connection) {
+				Ok(Some(placement)) => json(&board.dated(&placement, timestamps)).into_response(),
+				Ok(None) => StatusCode::NOT_FOUND.into_response(),
+				Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			}
 		})
 }
 
+pub fn placeable(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("pixels"))
+		.and(warp::path::param())
+		.and(warp::path("placeable"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPixelsGet)))
+		.and(database::connection(Arc::clone(&database_pool)))
+		.map(|board: PassableBoard, position, user: AuthedUser, mut connection| {
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			let user = User::from(user);
+
+			match board.placeability(&user, position, &mut connection) {
+				Ok(placeability) => json(&placeability).into_response(),
+				Err(err) => err.into_response(),
+			}
+		})
+}
+
+pub fn delete(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("pixels"))
+		.and(warp::path::param())
+		.and(warp::path::end())
+		.and(warp::delete())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPixelsDelete)))
+		.and(database::connection(Arc::clone(&database_pool)))
+		.map(|board: PassableBoard, position, user: AuthedUser, mut connection| {
+			let board = board.write();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			match board.clear_pixel(position, &mut connection) {
+				Ok(Some(_)) => StatusCode::NO_CONTENT.into_response(),
+				Ok(None) => StatusCode::NOT_FOUND.into_response(),
+				Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			}
+		})
+}
+
+/// Room for each looked up position in a request body: the longest u64 is 20
+/// digits, which leaves space for a separator and some whitespace.
+const LOOKUP_BYTES_PER_POSITION: u64 = 24;
+
+pub fn lookup(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("pixels"))
+		.and(warp::path("lookup"))
+		.and(warp::path::end())
+		.and(warp::post())
+		.and(warp::body::content_length_limit(
+			(CONFIG.max_lookup_positions as u64 + 1) * LOOKUP_BYTES_PER_POSITION,
+		))
+		.and(warp::body::json())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPixelsGet)))
+		.and(warp::query())
+		.and(database::connection(Arc::clone(&database_pool)))
+		.map(|
+			board: PassableBoard,
+			positions: Vec<u64>,
+			user,
+			timestamps: TimestampOptions,
+			mut connection,
+		| {
+			if positions.len() > CONFIG.max_lookup_positions {
+				return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+			}
+
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			match board.lookup_many(&positions, &mut connection) {
+				Ok(placements) => {
+					let placements = placements
+						.iter()
+						.map(|placement| {
+							placement
+								.as_ref()
+								.map(|placement| board.dated(placement, timestamps))
+						})
+						.collect::<Vec<_>>();
+
+					json(&placements).into_response()
+				},
+				Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			}
+		})
+}
+
+/// Check the user may place, allowing anonymous users if configured.
+fn can_place(user: AuthedUser) -> future::Ready<Result<AuthedUser, Rejection>> {
+	if CONFIG.allow_anonymous_place && user == AuthedUser::None {
+		future::ok(user)
+	} else {
+		with_permission(Permission::BoardsPixelsPost)(user)
+	}
+}
+
+#[derive(serde::Deserialize)]
+pub struct PlaceOptions {
+	/// Check whether the placement would succeed without making it.
+	#[serde(default)]
+	dry_run: bool,
+}
+
+/// The outcome of a successful dry run.
+#[derive(serde::Serialize)]
+struct DryRun {
+	/// How many pixels the placement would change.
+	changes: usize,
+}
+
+/// Rate limit placements. Dry runs are only checked against the limit so
+/// that they don't use up placements.
+fn limit_placement(
+	options: PlaceOptions,
+	user: AuthedUser,
+	address: Option<SocketAddr>,
+) -> future::Ready<Result<(PlaceOptions, AuthedUser), Rejection>> {
+	match rate_limit::limit(&PLACEMENT_LIMITER, &user, address, !options.dry_run) {
+		Ok(()) => future::ok((options, user)),
+		Err(limited) => future::err(warp::reject::custom(limited)),
+	}
+}
+
 pub fn post(
 	boards: BoardDataMap,
 	database_pool: Arc<Pool>,
@@ -94,15 +382,78 @@ pub fn post(
 		.and(warp::path::param())
 		.and(warp::path::end())
 		.and(warp::post())
+		.and(warp::body::content_length_limit(CONFIG.max_placement_body_size))
 		.and(warp::body::json())
-		.and(authorization::bearer().and_then(with_permission(Permission::BoardsPixelsPost)))
+		.and(warp::header::optional::<String>("idempotency-key"))
+		// NOTE: limiting must happen before the board is locked so that
+		// limited requests can't contend with valid ones.
+		.and(
+			serde_qs::warp::query(Default::default())
+				.and(authorization::bearer().and_then(can_place))
+				.and(warp::addr::remote())
+				.and_then(limit_placement)
+				.untuple_one(),
+		)
 		.and(database::connection(Arc::clone(&database_pool)))
-		.map(|board: PassableBoard, position, placement: PlacementRequest, user, mut connection| {
-			let user =
-				Option::from(user).expect("Default user shouldn't have place permisisons");
+		.map(|
+			board: PassableBoard,
+			position,
+			placement: PlacementRequest,
+			idempotency_key: Option<String>,
+			options: PlaceOptions,
+			user,
+			mut connection,
+		| {
+			if idempotency_key
+				.as_ref()
+				.is_some_and(|key| key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH)
+			{
+				return StatusCode::BAD_REQUEST.into_response();
+			}
 
 			let board = board.write();
-			let board = board.as_ref().unwrap();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			// Anonymous users share a single identity (and cooldown).
+			let user = User::from(user);
+
+			if options.dry_run {
+				return match board.dry_place(&user, position, placement.color, &mut connection) {
+					Ok(changes) => {
+						match board.user_cooldown_info(&user, &mut connection) {
+							Ok(cooldown_info) => {
+								let mut response = json(&DryRun { changes }).into_response();
+								for (key, value) in cooldown_info.into_headers() {
+									response = warp::reply::with_header(response, key, value).into_response();
+								}
+								response
+							},
+							Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+						}
+					},
+					Err(err) => err.into_response(),
+				};
+			}
+
+			// NOTE: the board is locked at this point so requests with the same
+			// key can't both miss.
+			let idempotency_key = idempotency_key.map(|key| {
+				IdempotencyKey {
+					board: board.id,
+					user: user.id.clone(),
+					key,
+				}
+			});
+			if let Some(response) = idempotency_key.as_ref().and_then(|key| PLACEMENT_RESULTS.get(key)) {
+				return response;
+			}
+
 			let place_attempt = board.try_place(
 				// TODO: maybe accept option but make sure not to allow undos etc for anon
 				// users
@@ -114,22 +465,22 @@ pub fn post(
 
 			match place_attempt {
 				Ok(placement) => {
-					let cooldown_info = board
-						.user_cooldown_info(&user, &mut connection)
-						.unwrap();
-
-					let mut response = warp::reply::with_status(
-						json(&placement).into_response(),
-						StatusCode::CREATED,
-					)
-					.into_response();
-
-					for (key, value) in cooldown_info.into_headers() {
-						response =
-							warp::reply::with_header(response, key, value).into_response();
+					let cooldown_info = match board.user_cooldown_info(&user, &mut connection) {
+						Ok(cooldown_info) => cooldown_info,
+						Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+					};
+
+					let result = PlacementResult {
+						placed_at: Instant::now(),
+						placement,
+						headers: cooldown_info.into_headers(),
+					};
+
+					if let Some(key) = idempotency_key {
+						PLACEMENT_RESULTS.insert(key, result.clone());
 					}
 
-					response
+					result.into_response()
 				},
 				Err(err) => err.into_response(),
 			}
@@ -1,3 +1,5 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+
 use super::*;
 
 pub fn get(
@@ -11,9 +13,120 @@ pub fn get(
 		.and(warp::get())
 		.and(authorization::bearer().and_then(with_permission(Permission::BoardsUsers)))
 		.and(database::connection(database_pool))
-		.map(|board: PassableBoard, _user, mut connection| {
+		.map(|board: PassableBoard, user, mut connection| {
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			match board.user_count(&mut connection) {
+				Ok(user_count) => json(&user_count).into_response(),
+				Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			}
+		})
+}
+
+#[derive(serde::Serialize)]
+struct LeaderboardEntry {
+	user: String,
+	placements: usize,
+}
+
+pub fn leaderboard(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("leaderboard"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsUsers)))
+		.and(warp::query())
+		.and(database::connection(database_pool))
+		// NOTE: the leaderboard is a single page so only the limit is used.
+		.map(|board: PassableBoard, user, options: PaginationOptions<usize>, mut connection| {
 			let board = board.read();
-			let board = board.as_ref().unwrap();
-			json(&board.user_count(&mut connection).unwrap()).into_response()
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			match board.leaderboard(options.limit(), &mut connection) {
+				Ok(leaderboard) => {
+					let entries = leaderboard
+						.into_iter()
+						.map(|(user_id, placements)| {
+							LeaderboardEntry {
+								user: format!(
+									"/users/{}",
+									utf8_percent_encode(&user_id, NON_ALPHANUMERIC),
+								),
+								placements,
+							}
+						})
+						.collect::<Vec<_>>();
+
+					json(&entries).into_response()
+				},
+				Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+			}
+		})
+}
+
+pub fn pixels(
+	boards: BoardDataMap,
+	database_pool: Arc<Pool>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("boards")
+		.and(board::path::read(&boards))
+		.and(warp::path("users"))
+		.and(warp::path::param())
+		.and(warp::path("pixels"))
+		.and(warp::path::end())
+		.and(warp::get())
+		.and(authorization::bearer().and_then(with_permission(Permission::BoardsUsersPixelsList)))
+		.and(warp::query())
+		.and(warp::query())
+		.and(warp::query())
+		.and(database::connection(database_pool))
+		.map(|
+			board: PassableBoard,
+			user_id: String,
+			user,
+			options: PaginationOptions<PageToken>,
+			mut filter: PlacementFilter,
+			timestamps: TimestampOptions,
+			mut connection,
+		| {
+			let user_id = match percent_decode_str(&user_id).decode_utf8() {
+				Ok(user_id) => user_id.into_owned(),
+				Err(_) => return StatusCode::NOT_FOUND.into_response(),
+			};
+
+			let board = board.read();
+			let board = match board.as_ref() {
+				Some(board) => board,
+				None => return StatusCode::NOT_FOUND.into_response(),
+			};
+			if !board.visible_to(&user) {
+				return StatusCode::NOT_FOUND.into_response();
+			}
+
+			let base_uri = format!(
+				"/boards/{}/users/{}/pixels",
+				board.id,
+				utf8_percent_encode(&user_id, NON_ALPHANUMERIC),
+			);
+			filter.user = Some(user_id);
+
+			pixels::placement_page(board, options, &filter, timestamps, &base_uri, &mut connection)
 		})
 }
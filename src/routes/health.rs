@@ -0,0 +1,40 @@
+use std::{sync::Arc, time::Duration};
+
+use diesel::RunQueryDsl;
+
+use super::*;
+
+/// How long readiness checks wait for a database connection.
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Liveness: responds as long as the server is running.
+pub fn health() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("health")
+		.and(warp::path::end())
+		.and(warp::get())
+		.map(|| StatusCode::OK)
+}
+
+/// Readiness: responds only if the database is reachable.
+pub fn ready(database_pool: Arc<Pool>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path("ready")
+		.and(warp::path::end())
+		.and(warp::get())
+		.map(move || {
+			let reachable = database_pool
+				.get_timeout(READY_TIMEOUT)
+				.ok()
+				.and_then(|mut connection| {
+					diesel::sql_query("SELECT 1")
+						.execute(&mut connection)
+						.ok()
+				})
+				.is_some();
+
+			if reachable {
+				StatusCode::OK
+			} else {
+				StatusCode::SERVICE_UNAVAILABLE
+			}
+		})
+}
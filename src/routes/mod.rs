@@ -21,5 +21,7 @@ use crate::{
 	objects::*,
 };
 
+pub mod admin;
 pub mod auth;
 pub mod core;
+pub mod health;
@@ -0,0 +1,206 @@
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::config::CONFIG;
+use crate::objects::{Board, BoardInfo};
+
+lazy_static! {
+	static ref CLIENT: Client = Client::builder()
+		.timeout(Duration::from_secs(CONFIG.webhook_timeout))
+		.build()
+		.expect("Failed to build webhook client");
+}
+
+/// The things webhooks can be notified of.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+pub enum EventKind {
+	BoardCreated,
+	BoardPatched,
+	BoardDeleted,
+}
+
+impl EventKind {
+	fn subscribed(&self) -> bool {
+		CONFIG.webhook_events
+			.as_ref()
+			.is_none_or(|events| events.contains(self))
+	}
+}
+
+#[derive(Serialize)]
+struct Event<'l> {
+	event: EventKind,
+	board: String,
+	info: &'l BoardInfo,
+	timestamp: u64,
+}
+
+/// Notify every configured webhook of an event involving a board.
+/// Delivery happens in the background so this never blocks.
+pub fn notify(
+	kind: EventKind,
+	board: &Board,
+) {
+	if let Some(notification) = Notification::new(kind, board) {
+		notification.send();
+	}
+}
+
+/// An event which is ready to be sent to webhooks.
+/// This allows describing a board before an operation which consumes it and
+/// only notifying once the operation has succeeded.
+pub struct Notification {
+	body: Vec<u8>,
+}
+
+impl Notification {
+	/// None if no webhook wants the event.
+	pub fn new(
+		kind: EventKind,
+		board: &Board,
+	) -> Option<Self> {
+		if CONFIG.webhook_urls.is_empty() || !kind.subscribed() {
+			return None;
+		}
+
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap()
+			.as_secs();
+
+		let event = Event {
+			event: kind,
+			board: http::Uri::from(board).to_string(),
+			info: &board.info,
+			timestamp,
+		};
+
+		match serde_json::to_vec(&event) {
+			Ok(body) => Some(Self { body }),
+			Err(err) => {
+				tracing::error!(%err, "failed to serialize webhook event");
+				None
+			},
+		}
+	}
+
+	pub fn send(self) {
+		for url in &CONFIG.webhook_urls {
+			tokio::spawn(deliver(url.clone(), self.body.clone()));
+		}
+	}
+}
+
+async fn deliver(
+	url: Url,
+	body: Vec<u8>,
+) {
+	let delivered = retry(
+		CONFIG.webhook_retries,
+		Duration::from_secs(1),
+		|attempt| {
+			let url = url.clone();
+			let body = body.clone();
+			async move {
+				let response = CLIENT
+					.post(url.clone())
+					.header(http::header::CONTENT_TYPE, "application/json")
+					.body(body)
+					.send().await;
+
+				match response {
+					Ok(response) if response.status().is_success() => true,
+					Ok(response) => {
+						tracing::warn!(%url, status = %response.status(), attempt, "webhook rejected event");
+						false
+					},
+					Err(err) => {
+						tracing::warn!(%url, %err, attempt, "failed to deliver webhook event");
+						false
+					},
+				}
+			}
+		},
+	).await;
+
+	if !delivered {
+		tracing::error!(%url, "gave up delivering webhook event");
+	}
+}
+
+/// Make an attempt and then up to `retries` more until one succeeds,
+/// doubling `delay` between each. Returns whether any attempt succeeded.
+async fn retry<F, A>(
+	retries: u32,
+	mut delay: Duration,
+	mut attempt: F,
+) -> bool
+where
+	F: FnMut(u32) -> A,
+	A: Future<Output = bool>,
+{
+	for number in 0..=retries {
+		if number > 0 {
+			tokio::time::sleep(delay).await;
+			delay *= 2;
+		}
+
+		if attempt(number).await {
+			return true;
+		}
+	}
+
+	false
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::{AtomicU32, Ordering};
+
+	use super::*;
+
+	#[tokio::test]
+	async fn retries_until_an_attempt_succeeds() {
+		let attempts = AtomicU32::new(0);
+
+		let delivered = retry(3, Duration::ZERO, |number| {
+			attempts.fetch_add(1, Ordering::Relaxed);
+			async move { number == 2 }
+		}).await;
+
+		assert!(delivered);
+		assert_eq!(attempts.load(Ordering::Relaxed), 3);
+	}
+
+	#[tokio::test]
+	async fn gives_up_after_the_last_retry() {
+		let attempts = AtomicU32::new(0);
+
+		let delivered = retry(3, Duration::ZERO, |_| {
+			attempts.fetch_add(1, Ordering::Relaxed);
+			async { false }
+		}).await;
+
+		assert!(!delivered);
+		assert_eq!(attempts.load(Ordering::Relaxed), 4);
+	}
+
+	#[tokio::test]
+	async fn does_not_retry_a_success() {
+		let attempts = AtomicU32::new(0);
+
+		let delivered = retry(3, Duration::ZERO, |_| {
+			attempts.fetch_add(1, Ordering::Relaxed);
+			async { true }
+		}).await;
+
+		assert!(delivered);
+		assert_eq!(attempts.load(Ordering::Relaxed), 1);
+	}
+}